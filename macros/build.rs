@@ -0,0 +1,22 @@
+//! Sets `datadriven_nightly` when building with a nightly compiler, so that the `nightly` feature
+//! only turns on unstable APIs where they exist, and is harmless under `--all-features` on stable.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(datadriven_nightly)");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let nightly = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|out| {
+            let version = String::from_utf8_lossy(&out.stdout);
+            version.contains("nightly") || version.contains("-dev")
+        })
+        .unwrap_or(false);
+    if nightly {
+        println!("cargo:rustc-cfg=datadriven_nightly");
+    }
+}
@@ -0,0 +1,167 @@
+//! Procedural macros for datadriven. These are re-exported by the `datadriven` crate, and
+//! documented there.
+
+#![cfg_attr(
+    all(feature = "nightly", datadriven_nightly),
+    feature(proc_macro_tracked_path)
+)]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Error, Ident, ItemFn, LitStr};
+
+/// Generates a `#[test]` for each test file under a directory, which runs the file with the
+/// function this is attached to. See `datadriven::test`.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(attr as LitStr);
+    let handler = parse_macro_input!(item as ItemFn);
+    match expand(&dir, &handler) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+// The test files in a directory, laid out the way the generated tests will be.
+#[derive(Default)]
+struct Module {
+    // Test names, and the path of the file each one runs, relative to the crate root.
+    files: BTreeMap<String, String>,
+    dirs: BTreeMap<String, Module>,
+}
+
+fn expand(dir: &LitStr, handler: &ItemFn) -> syn::Result<TokenStream2> {
+    let span = dir.span();
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| Error::new(span, "CARGO_MANIFEST_DIR isn't set"))?;
+    let manifest_dir = Path::new(&manifest_dir);
+    let relative = dir.value().trim_end_matches('/').to_string();
+    let root = manifest_dir.join(&relative);
+
+    let mut module = Module::default();
+    if root.is_dir() {
+        read_dir(&root, &relative, &mut module, span)?;
+    } else if root.is_file() {
+        let name = relative.rsplit('/').next().unwrap_or(&relative);
+        module
+            .files
+            .insert(test_name(name, span)?, relative.clone());
+    } else {
+        return Err(Error::new(
+            span,
+            format!("{} doesn't exist", root.display()),
+        ));
+    }
+
+    let name = &handler.sig.ident;
+    let vis = &handler.vis;
+    let tests = module_tokens(&module, name, manifest_dir, 1);
+    // Functions and modules live in different namespaces, so the tests can go in a module with
+    // the same name as the handler, which makes them show up as `handler::path::to::file`.
+    Ok(quote! {
+        #handler
+
+        #vis mod #name {
+            #tests
+        }
+    })
+}
+
+fn read_dir(dir: &Path, relative: &str, module: &mut Module, span: Span) -> syn::Result<()> {
+    // A directory changes when files are added to or removed from it, so tracking it rebuilds the
+    // tests when that happens. Stable compilers can only track files, through `include_bytes!`.
+    #[cfg(all(feature = "nightly", datadriven_nightly))]
+    proc_macro::tracked::path(dir);
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| Error::new(span, format!("reading {}: {}", dir.display(), err)))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if should_ignore_file(&file_name) {
+            continue;
+        }
+        let path = format!("{}/{}", relative, file_name);
+        let name = test_name(&file_name, span)?;
+        if entry.path().is_dir() {
+            let mut sub = Module::default();
+            read_dir(&entry.path(), &path, &mut sub, span)?;
+            if module.dirs.insert(name.clone(), sub).is_some() {
+                return Err(collision(&name, span));
+            }
+        } else if module.files.insert(name.clone(), path).is_some() {
+            return Err(collision(&name, span));
+        }
+    }
+    Ok(())
+}
+
+fn collision(name: &str, span: Span) -> Error {
+    Error::new(
+        span,
+        format!("more than one test file would be named {}", name),
+    )
+}
+
+// The same files `datadriven::walk` ignores: .XXX, XXX~ and #XXX#.
+fn should_ignore_file(name: &str) -> bool {
+    name.starts_with('.') || name.ends_with('~') || name.starts_with('#') && name.ends_with('#')
+}
+
+// Turns a file name into an identifier, replacing anything that can't be in one with `_`.
+fn test_name(file_name: &str, span: Span) -> syn::Result<String> {
+    let mut name: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    // Keywords, and `_` on its own.
+    if syn::parse_str::<Ident>(&name).is_err() {
+        name.push('_');
+    }
+    syn::parse_str::<Ident>(&name)
+        .map(|_| name)
+        .map_err(|_| Error::new(span, format!("can't name a test after {}", file_name)))
+}
+
+fn module_tokens(
+    module: &Module,
+    handler: &Ident,
+    manifest_dir: &Path,
+    depth: usize,
+) -> TokenStream2 {
+    let tests = module.files.iter().map(|(name, path)| {
+        let supers = (0..depth).map(|_| quote! { super:: });
+        let name = Ident::new(name, Span::call_site());
+        let absolute = manifest_dir.join(path).to_string_lossy().to_string();
+        // Including the file makes the test get rebuilt when it changes.
+        quote! {
+            #[test]
+            fn #name() {
+                const _: &[u8] = include_bytes!(#absolute);
+                ::datadriven::Walk::new(#path).run(#(#supers)* #handler);
+            }
+        }
+    });
+    let dirs = module.dirs.iter().map(|(name, sub)| {
+        let name = Ident::new(name, Span::call_site());
+        let body = module_tokens(sub, handler, manifest_dir, depth + 1);
+        quote! {
+            mod #name {
+                #body
+            }
+        }
+    });
+    quote! {
+        #(#tests)*
+        #(#dirs)*
+    }
+}
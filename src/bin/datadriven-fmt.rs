@@ -0,0 +1,64 @@
+//! Formats datadriven test files into canonical form.
+//!
+//! Usage: datadriven-fmt [--check] [--sort-args] <path>...
+//!
+//! With `--check`, nothing is written and the exit status is nonzero if any file is not already
+//! formatted.
+
+use std::path::Path;
+use std::process::exit;
+
+use datadriven::fmt::{check_path, format_path, FormatOptions};
+
+fn main() {
+    let mut check = false;
+    let mut opts = FormatOptions::default();
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--sort-args" => opts.sort_args = true,
+            "-h" | "--help" => {
+                println!("usage: datadriven-fmt [--check] [--sort-args] <path>...");
+                return;
+            }
+            flag if flag.starts_with("--") => {
+                eprintln!("unknown flag: {}", flag);
+                exit(2);
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("usage: datadriven-fmt [--check] [--sort-args] <path>...");
+        exit(2);
+    }
+
+    let mut unformatted = false;
+    for path in paths {
+        let result = if check {
+            check_path(Path::new(&path), &opts)
+        } else {
+            format_path(Path::new(&path), &opts)
+        };
+        match result {
+            Ok(changed) => {
+                for file in changed {
+                    if check {
+                        println!("would reformat {}", file.display());
+                        unformatted = true;
+                    } else {
+                        println!("reformatted {}", file.display());
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("error: {}", err);
+                exit(2);
+            }
+        }
+    }
+    if unformatted {
+        exit(1);
+    }
+}
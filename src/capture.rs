@@ -0,0 +1,43 @@
+//! Capturing what datadriven prints while a test file runs, so that `datadriven::harness` can show
+//! it only for the tests that fail (or with `--show-output`), the way libtest does.
+//!
+//! Only what datadriven prints through `eprintln` here is captured, along with panic messages once
+//! the harness has hooked them. There's no stable way to capture what a handler prints itself.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+
+thread_local! {
+    // What has been captured on this thread, if it is capturing.
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Starts capturing output on this thread.
+pub(crate) fn start() {
+    CAPTURED.with(|c| *c.borrow_mut() = Some(String::new()));
+}
+
+/// Stops capturing output on this thread, returning what was captured.
+pub(crate) fn finish() -> String {
+    CAPTURED.with(|c| c.borrow_mut().take()).unwrap_or_default()
+}
+
+/// Adds `args` to what this thread is capturing, returning whether it is capturing at all.
+pub(crate) fn captured(args: fmt::Arguments<'_>) -> bool {
+    CAPTURED.with(|c| match &mut *c.borrow_mut() {
+        Some(captured) => {
+            let _ = fmt::Write::write_fmt(captured, args);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Prints a line to stderr, or captures it if this thread is capturing. Not `eprintln!`, since
+/// libtest would hide that for a passing test.
+pub(crate) fn eprintln(args: fmt::Arguments<'_>) {
+    if !captured(format_args!("{}\n", args)) {
+        let _ = writeln!(io::stderr(), "{}", args);
+    }
+}
@@ -0,0 +1,297 @@
+//! Line-based unified diffs for showing how actual output differs from what was expected.
+
+use std::env;
+use std::io::IsTerminal;
+
+// How many unchanged lines to show around each change.
+const CONTEXT: usize = 3;
+
+// The most entries the table for finding the longest common subsequence can have, which takes
+// memory proportional to the product of the numbers of lines that differ. Past this, the lines that
+// differ are shown as all of the expected ones replaced by all of the actual ones.
+const MAX_LCS_TABLE: usize = 1 << 22;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const REVERSE: &str = "\x1b[7m";
+const UNREVERSE: &str = "\x1b[27m";
+const RESET: &str = "\x1b[0m";
+
+// Whether diffs should be colored. Failures end up on stderr, so that's what gets checked.
+pub(crate) fn use_color() -> bool {
+    let no_color = matches!(env::var_os("NO_COLOR"), Some(v) if !v.is_empty());
+    !no_color && std::io::stderr().is_terminal()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edit {
+    op: Op,
+    // Indexes into the old and new lines. Only `old` is meaningful for a delete and only `new` for
+    // an insert.
+    old: usize,
+    new: usize,
+}
+
+/// Renders a unified diff from `expected` to `actual`. Lines are numbered starting from
+/// `first_line`, which should be the line in the test file where the expected output begins.
+/// Returns an empty string if there are no differences.
+pub(crate) fn unified_diff(expected: &str, actual: &str, first_line: usize, color: bool) -> String {
+    let old: Vec<&str> = expected.split_inclusive('\n').collect();
+    let new: Vec<&str> = actual.split_inclusive('\n').collect();
+    let edits = diff_lines(&old, &new);
+    if edits.iter().all(|e| e.op == Op::Equal) {
+        return String::new();
+    }
+
+    let mut out = String::new();
+    out.push_str(&paint(color, RED, "--- expected"));
+    out.push('\n');
+    out.push_str(&paint(color, GREEN, "+++ actual"));
+    out.push('\n');
+    for (start, end) in hunks(&edits) {
+        let hunk = &edits[start..end];
+        let (old_start, old_len) = span(hunk, Op::Insert, |e| e.old, first_line);
+        let (new_start, new_len) = span(hunk, Op::Delete, |e| e.new, first_line);
+        let header = format!(
+            "@@ -{},{} +{},{} @@",
+            old_start, old_len, new_start, new_len
+        );
+        out.push_str(&paint(color, CYAN, &header));
+        out.push('\n');
+
+        let mut i = 0;
+        while i < hunk.len() {
+            if hunk[i].op == Op::Equal {
+                push_line(&mut out, ' ', &visible(old[hunk[i].old], false));
+                i += 1;
+                continue;
+            }
+            // Gather up a run of deletes followed by a run of inserts.
+            let deletes: Vec<&str> = hunk[i..]
+                .iter()
+                .take_while(|e| e.op == Op::Delete)
+                .map(|e| old[e.old])
+                .collect();
+            i += deletes.len();
+            let inserts: Vec<&str> = hunk[i..]
+                .iter()
+                .take_while(|e| e.op == Op::Insert)
+                .map(|e| new[e.new])
+                .collect();
+            i += inserts.len();
+            render_change(&mut out, &deletes, &inserts, color);
+        }
+    }
+    out
+}
+
+fn render_change(out: &mut String, deletes: &[&str], inserts: &[&str], color: bool) {
+    if let ([old], [new]) = (deletes, inserts) {
+        // If only whitespace changed, show all of it.
+        let whitespace_only = strip_whitespace(old) == strip_whitespace(new);
+        let (old, new) = (visible(old, whitespace_only), visible(new, whitespace_only));
+        let (old_chars, new_chars): (Vec<char>, Vec<char>) =
+            (old.chars().collect(), new.chars().collect());
+        let prefix = old_chars
+            .iter()
+            .zip(new_chars.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let max_suffix = old_chars.len().min(new_chars.len()) - prefix;
+        let suffix = old_chars
+            .iter()
+            .rev()
+            .zip(new_chars.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if color {
+            push_line(out, '-', &highlight(&old_chars, prefix, suffix, RED));
+            push_line(out, '+', &highlight(&new_chars, prefix, suffix, GREEN));
+        } else {
+            push_line(out, '-', &old);
+            push_line(out, '+', &new);
+            let width = (new_chars.len() - prefix - suffix).max(1);
+            push_line(
+                out,
+                ' ',
+                &format!("{}{}", " ".repeat(prefix), "^".repeat(width)),
+            );
+        }
+        return;
+    }
+    for line in deletes {
+        push_line(out, '-', &paint(color, RED, &visible(line, false)));
+    }
+    for line in inserts {
+        push_line(out, '+', &paint(color, GREEN, &visible(line, false)));
+    }
+}
+
+fn highlight(chars: &[char], prefix: usize, suffix: usize, color: &str) -> String {
+    let end = chars.len() - suffix;
+    let s = |range: std::ops::Range<usize>| chars[range].iter().collect::<String>();
+    format!(
+        "{}{}{}{}{}{}{}",
+        color,
+        s(0..prefix),
+        REVERSE,
+        s(prefix..end),
+        UNREVERSE,
+        s(end..chars.len()),
+        RESET
+    )
+}
+
+// Renders a line (which may or may not end in a newline) for display. Tabs and trailing spaces
+// are always made visible. With `all_whitespace`, every space and the newline are too.
+fn visible(line: &str, all_whitespace: bool) -> String {
+    let (text, newline) = match line.strip_suffix('\n') {
+        Some(text) => (text, true),
+        None => (line, false),
+    };
+    let trailing_start = text.trim_end_matches(' ').len();
+    let mut s = String::new();
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '\t' => s.push('→'),
+            ' ' if all_whitespace || i >= trailing_start => s.push('·'),
+            ch => s.push(ch),
+        }
+    }
+    if newline && all_whitespace {
+        s.push('⏎');
+    }
+    s
+}
+
+fn strip_whitespace(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn push_line(out: &mut String, marker: char, line: &str) {
+    out.push(marker);
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn paint(color: bool, code: &str, s: &str) -> String {
+    if color {
+        format!("{}{}{}", code, s, RESET)
+    } else {
+        s.to_string()
+    }
+}
+
+// The first line number and the number of lines a hunk covers on one side of the diff.
+fn span<F>(hunk: &[Edit], skip: Op, idx: F, first_line: usize) -> (usize, usize)
+where
+    F: Fn(&Edit) -> usize,
+{
+    let mut lines = hunk.iter().filter(|e| e.op != skip);
+    match lines.next() {
+        Some(e) => (first_line + idx(e), 1 + lines.count()),
+        // Nothing on this side, so point at where the change happens.
+        None => (first_line + idx(&hunk[0]), 0),
+    }
+}
+
+// Groups the edits into hunks of changes with surrounding context, returning the range of edits
+// each hunk covers.
+fn hunks(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (i, _) in edits.iter().enumerate().filter(|(_, e)| e.op != Op::Equal) {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + 1 + CONTEXT).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    hunks
+}
+
+// Computes a line-based edit script using the longest common subsequence of the middle section
+// left after trimming the common prefix and suffix, if that isn't too big (see `MAX_LCS_TABLE`).
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let lcs = if (a.len() + 1).saturating_mul(b.len() + 1) <= MAX_LCS_TABLE {
+        let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        Some(lcs)
+    } else {
+        None
+    };
+
+    let mut edits: Vec<Edit> = (0..prefix)
+        .map(|i| Edit {
+            op: Op::Equal,
+            old: i,
+            new: i,
+        })
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        // Deletes are preferred over inserts, so that changed lines read old-then-new.
+        let edit = match &lcs {
+            Some(lcs) if i < a.len() && j < b.len() && a[i] == b[j] => Op::Equal,
+            Some(lcs) if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) => {
+                Op::Delete
+            }
+            Some(_) => Op::Insert,
+            None if i < a.len() => Op::Delete,
+            None => Op::Insert,
+        };
+        edits.push(Edit {
+            op: edit,
+            old: prefix + i,
+            new: prefix + j,
+        });
+        match edit {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    edits.extend((0..suffix).map(|k| Edit {
+        op: Op::Equal,
+        old: old.len() - suffix + k,
+        new: new.len() - suffix + k,
+    }));
+    edits
+}
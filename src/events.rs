@@ -0,0 +1,141 @@
+//! A stream of JSON events, one per line, written while a walk runs so that a supervising process
+//! can follow along. See `Walk::events`.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::harness::json_string;
+use crate::{CaseResult, FileReport, Outcome, Report, Reporter, TestCase};
+
+// The files events are being written to. Every walk in a binary that writes to the same path
+// shares the same file, so that their events are interleaved rather than overwriting each other.
+static FILES: Mutex<Option<HashMap<PathBuf, Arc<Mutex<File>>>>> = Mutex::new(None);
+
+/// Where events go.
+#[derive(Debug, Clone)]
+pub(crate) enum Events {
+    Stdout,
+    File(Arc<Mutex<File>>),
+}
+
+impl Events {
+    /// Events written to `path`, or to stdout if `path` is `-`. Events are appended to the file,
+    /// since the other test binaries `cargo test` runs write to it too.
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        if path == Path::new("-") {
+            return Ok(Events::Stdout);
+        }
+        let mut files = FILES.lock().unwrap_or_else(|err| err.into_inner());
+        let files = files.get_or_insert_with(HashMap::new);
+        if let Some(file) = files.get(path) {
+            return Ok(Events::File(file.clone()));
+        }
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let file = Arc::new(Mutex::new(file));
+        files.insert(path.to_path_buf(), file.clone());
+        Ok(Events::File(file))
+    }
+
+    // Writes out one event. Events are best-effort, so errors writing them are ignored rather than
+    // failing the walk.
+    fn emit(&self, mut event: String) {
+        event.push('\n');
+        let _ = match self {
+            Events::Stdout => {
+                let mut out = io::stdout().lock();
+                out.write_all(event.as_bytes()).and_then(|_| out.flush())
+            }
+            Events::File(file) => file
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .write_all(event.as_bytes()),
+        };
+    }
+}
+
+impl Reporter for Events {
+    fn on_file_start(&self, filename: &str) {
+        self.emit(format!(
+            r#"{{"event":"file_started","file":{}}}"#,
+            json_string(filename)
+        ));
+    }
+
+    fn on_case_start(&self, filename: &str, case: &TestCase) {
+        self.emit(format!(
+            r#"{{"event":"case_started","file":{},"line":{},"directive":{}}}"#,
+            json_string(filename),
+            case.line(),
+            json_string(&case.directive)
+        ));
+    }
+
+    fn on_case_result(&self, filename: &str, case: &CaseResult) {
+        let (event, extra) = match &case.outcome {
+            Outcome::Passed => ("case_passed", String::new()),
+            Outcome::Failed(actual) => (
+                "case_failed",
+                format!(r#","actual":{}"#, json_string(actual)),
+            ),
+            Outcome::Errored(err) => ("case_errored", format!(r#","error":{}"#, json_string(err))),
+            Outcome::Skipped => ("case_skipped", String::new()),
+            Outcome::Rewritten(actual) => (
+                "case_rewritten",
+                format!(r#","actual":{}"#, json_string(actual)),
+            ),
+        };
+        self.emit(format!(
+            r#"{{"event":"{}","file":{},"line":{},"directive":{},"input":{},"expected":{}{},"duration_ms":{}}}"#,
+            event,
+            json_string(filename),
+            case.line,
+            json_string(&case.directive),
+            json_string(&case.input),
+            json_string(&case.expected),
+            extra,
+            millis(case.duration)
+        ));
+    }
+
+    fn on_rewrite(&self, filename: &str, _contents: &str) {
+        self.emit(format!(
+            r#"{{"event":"file_rewritten","file":{}}}"#,
+            json_string(filename)
+        ));
+    }
+
+    fn on_file_finish(&self, file: &FileReport) {
+        let failures: Vec<_> = file.failures.iter().map(|f| json_string(f)).collect();
+        self.emit(format!(
+            r#"{{"event":"file_finished","file":{},"passed":{},"failures":[{}],"duration_ms":{}}}"#,
+            json_string(&file.filename),
+            file.failures.is_empty(),
+            failures.join(","),
+            millis(file.duration)
+        ));
+    }
+
+    fn on_finish(&self, report: &Report) {
+        let summary = report.summary();
+        self.emit(format!(
+            r#"{{"event":"summary","passed":{},"files":{},"cases":{},"cases_passed":{},"cases_failed":{},"cases_skipped":{},"rewritten":{},"errors":[{}],"duration_ms":{}}}"#,
+            report.passed(),
+            summary.files,
+            summary.cases,
+            summary.passed,
+            summary.failed,
+            summary.skipped,
+            summary.rewritten,
+            report.errors.iter().map(|e| json_string(e)).collect::<Vec<_>>().join(","),
+            millis(summary.duration)
+        ));
+    }
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.3}", d.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,219 @@
+//! Narrowing a walk down to particular test files and test cases, with `RUN` (a path, glob or
+//! regex, optionally followed by `:42` or `/subtest`), `DATADRIVEN_DIRECTIVE` and
+//! `DATADRIVEN_CASE`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+
+use crate::{test_files, TestCase};
+
+/// Which test cases in a file to compare. Empty matches everything.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CaseFilter {
+    // From `RUN=file:42`.
+    line: Option<usize>,
+    // From `RUN=file/subtest`.
+    name: Option<String>,
+    directive: Option<Regex>,
+    input: Option<Regex>,
+    // Why any of the variables couldn't be used.
+    errors: Vec<String>,
+}
+
+// What to do with a test case, given the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Selection {
+    // Run it and compare its output.
+    Compare,
+    // Run it for the sake of any state later test cases need, but ignore its output.
+    RunOnly,
+    // Don't run it at all.
+    Skip,
+}
+
+impl CaseFilter {
+    /// Reads the filter from the environment. `dir` is what `RUN` is relative to.
+    pub(crate) fn from_env(dir: &str) -> Self {
+        let (line, name) = match env::var("RUN") {
+            Ok(run) => match split_run(Path::new(dir), &run).1 {
+                Some(CaseSelector::Line(line)) => (Some(line), None),
+                Some(CaseSelector::Name(name)) => (None, Some(name)),
+                None => (None, None),
+            },
+            Err(_) => (None, None),
+        };
+        let mut errors = Vec::new();
+        CaseFilter {
+            line,
+            name,
+            directive: regex_from_env("DATADRIVEN_DIRECTIVE", &mut errors),
+            input: regex_from_env("DATADRIVEN_CASE", &mut errors),
+            errors,
+        }
+    }
+
+    /// What was wrong with the environment variables, if anything. A walk with a broken filter
+    /// doesn't run, rather than running more than was asked for.
+    pub(crate) fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.line.is_none()
+            && self.name.is_none()
+            && self.directive.is_none()
+            && self.input.is_none()
+    }
+
+    fn matches(&self, case: &TestCase) -> bool {
+        if self.line.is_some_and(|line| line != case.line_number) {
+            return false;
+        }
+        if let Some(name) = &self.name {
+            let at_line = format!("{}@{}", case.directive, case.line_number);
+            if *name != case.directive && *name != at_line {
+                return false;
+            }
+        }
+        if let Some(re) = &self.directive {
+            if !re.is_match(&case.directive) {
+                return false;
+            }
+        }
+        if let Some(re) = &self.input {
+            if !re.is_match(&case.input) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Works out what to do with each of `cases`. Everything up to the last selected test case
+    /// still runs, so that stateful files build up the same state they would in a full run.
+    pub(crate) fn select<'a, I>(&self, cases: I) -> Vec<Selection>
+    where
+        I: Iterator<Item = &'a TestCase>,
+    {
+        let selected: Vec<bool> = cases.map(|case| self.matches(case)).collect();
+        if self.is_empty() {
+            return vec![Selection::Compare; selected.len()];
+        }
+        let last = selected.iter().rposition(|s| *s);
+        selected
+            .iter()
+            .enumerate()
+            .map(|(i, selected)| match last {
+                _ if *selected => Selection::Compare,
+                Some(last) if i < last => Selection::RunOnly,
+                _ => Selection::Skip,
+            })
+            .collect()
+    }
+}
+
+fn regex_from_env(var: &str, errors: &mut Vec<String>) -> Option<Regex> {
+    let pattern = env::var(var).ok().filter(|p| !p.is_empty())?;
+    match Regex::new(&pattern) {
+        Ok(re) => Some(re),
+        Err(err) => {
+            errors.push(format!("{} is not a valid regex: {}", var, err));
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CaseSelector {
+    Line(usize),
+    Name(String),
+}
+
+/// Splits the value of `RUN` into the path under `dir` it names, and the test cases within that
+/// path it picks out, if any. A `RUN` that names an existing path as a whole is always taken as
+/// just a path.
+pub(crate) fn split_run(dir: &Path, run: &str) -> (PathBuf, Option<CaseSelector>) {
+    let whole = dir.join(run);
+    if whole.exists() {
+        return (whole, None);
+    }
+    if let Some((file, line)) = run.rsplit_once(':') {
+        if let Ok(line) = line.parse() {
+            if dir.join(file).is_file() {
+                return (dir.join(file), Some(CaseSelector::Line(line)));
+            }
+        }
+    }
+    // The test file is the longest prefix that is a file, and whatever is left names test cases.
+    let mut split = run.len();
+    while let Some(idx) = run[..split].rfind('/') {
+        let (file, name) = (&run[..idx], &run[idx + 1..]);
+        if dir.join(file).is_file() {
+            return (dir.join(file), Some(CaseSelector::Name(name.to_string())));
+        }
+        split = idx;
+    }
+    (whole, None)
+}
+
+/// Finds the test files under `dir` that `RUN` picks out. `RUN` can name a file or directory
+/// relative to `dir`, be a glob like `nested/*`, or be a regex prefixed with `re:`. Globs and
+/// regexes are matched against paths relative to `dir`, with `/` between components.
+///
+/// Fails if nothing matches, so that a typo can't turn into a passing run of no tests at all.
+pub(crate) fn run_files(dir: &Path, run: &str) -> Result<Vec<PathBuf>, String> {
+    let (path, _) = split_run(dir, run);
+    let files = if path.is_dir() {
+        listed(&path)?
+    } else if path.is_file() {
+        vec![path]
+    } else if let Some(pattern) = run.strip_prefix("re:") {
+        let re = Regex::new(pattern).map_err(|err| format!("RUN is not a valid regex: {}", err))?;
+        matching(dir, |relative| re.is_match(relative))?
+    } else if run.contains(['*', '?', '[']) {
+        let pattern =
+            Pattern::new(run).map_err(|err| format!("RUN is not a valid glob: {}", err))?;
+        let opts = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        matching(dir, |relative| pattern.matches_with(relative, opts))?
+    } else {
+        vec![]
+    };
+    if files.is_empty() {
+        return Err(format!(
+            "RUN={} doesn't match any test files in {}",
+            run,
+            dir.display()
+        ));
+    }
+    Ok(files)
+}
+
+// The test files under `dir` whose paths relative to `dir` satisfy `matches`.
+fn matching<M>(dir: &Path, matches: M) -> Result<Vec<PathBuf>, String>
+where
+    M: Fn(&str) -> bool,
+{
+    let files = if dir.is_dir() { listed(dir)? } else { vec![] };
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            let relative = file.strip_prefix(dir).unwrap_or(file);
+            let relative: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            matches(&relative.join("/"))
+        })
+        .collect())
+}
+
+// The test files under `dir`, with an error that says where they were being looked for.
+fn listed(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    test_files(dir.to_path_buf())
+        .map_err(|err| format!("listing test files in {}: {}", dir.display(), err))
+}
@@ -0,0 +1,181 @@
+//! Canonical formatting for test files.
+//!
+//! Formatting normalizes the whitespace on directive lines, collapses runs of blank lines, and
+//! re-emits every expected block the same way a rewrite would, so that the separator style always
+//! matches the content. The result is re-parsed and compared against the original to make sure
+//! that no test case or comment changed or went missing. Files with text the parser skips, like a
+//! line directly after a double-separator block, are refused rather than losing it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{test_files, write_atomic, write_result};
+use crate::{DataDrivenError, DirectiveParser, Stanza};
+use crate::{TestCase, TestFile};
+
+/// Options that control how test files are formatted.
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    /// Sort the arguments on each directive line by name.
+    pub sort_args: bool,
+}
+
+/// Formats the contents of a test file, returning the canonical form.
+pub fn format(contents: &str, opts: &FormatOptions) -> Result<String, DataDrivenError> {
+    let stanzas = TestFile::parse(contents)?;
+    for case in cases(&stanzas) {
+        if let Some(line) = case.layout.stray_line {
+            Err(DataDrivenError::Parse(
+                "this line directly follows a double-separator block, so it's ignored, and \
+                 formatting would delete it"
+                    .into(),
+            )
+            .with_line(line))?;
+        }
+    }
+    let mut s = String::new();
+    // Blank lines are held back until we see what comes after them, so that runs of them can be
+    // collapsed and they can be dropped from the start and end of the file.
+    let mut pending_blank = false;
+    for stanza in &stanzas {
+        let line = match stanza {
+            Stanza::Comment(c) => {
+                let c = c.trim_end();
+                if c.is_empty() {
+                    pending_blank = !s.is_empty();
+                    continue;
+                }
+                c.to_string()
+            }
+            Stanza::Test(case) => format_directive(case, opts)?,
+        };
+        if pending_blank {
+            s.push('\n');
+            pending_blank = false;
+        }
+        s.push_str(&line);
+        s.push('\n');
+        if let Stanza::Test(case) = stanza {
+            s.push_str(&case.input);
+            write_result(&mut s, case.expected.clone());
+        }
+    }
+
+    let reformatted = TestFile::parse(&s)?;
+    if !same_meaning(&stanzas, &reformatted) {
+        Err(DataDrivenError::Parse(
+            "formatting would change the meaning of the file".into(),
+        ))?;
+    }
+    Ok(s)
+}
+
+/// Formats every test file under `path` (or just `path`, if it is a file) in place. Returns the
+/// files that were changed.
+pub fn format_path(path: &Path, opts: &FormatOptions) -> Result<Vec<PathBuf>, DataDrivenError> {
+    visit(path, opts, |file, formatted| {
+        write_atomic(file, formatted).map_err(DataDrivenError::Io)
+    })
+}
+
+/// Checks that every test file under `path` (or just `path`, if it is a file) is already in
+/// canonical form, without modifying anything. Returns the files that would be changed by
+/// `format_path`.
+pub fn check_path(path: &Path, opts: &FormatOptions) -> Result<Vec<PathBuf>, DataDrivenError> {
+    visit(path, opts, |_, _| Ok(()))
+}
+
+fn visit<F>(path: &Path, opts: &FormatOptions, mut f: F) -> Result<Vec<PathBuf>, DataDrivenError>
+where
+    F: FnMut(&Path, &str) -> Result<(), DataDrivenError>,
+{
+    let files = if path.is_dir() {
+        test_files(path.to_path_buf())?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let mut changed = Vec::new();
+    for file in files {
+        let with_filename = |e: DataDrivenError| e.with_filename(file.display().to_string());
+        let contents = fs::read_to_string(&file)
+            .map_err(DataDrivenError::Io)
+            .map_err(with_filename)?;
+        let formatted = format(&contents, opts).map_err(with_filename)?;
+        if formatted != contents {
+            f(&file, &formatted).map_err(with_filename)?;
+            changed.push(file);
+        }
+    }
+    Ok(changed)
+}
+
+fn format_directive(case: &TestCase, opts: &FormatOptions) -> Result<String, DataDrivenError> {
+    // Comments on directive lines are kept, but only the part before them is parsed.
+    let (directive_line, comment) = match case.directive_line.find('#') {
+        Some(idx) => case.directive_line.split_at(idx),
+        None => (case.directive_line.as_str(), ""),
+    };
+    let (directive, mut args) = DirectiveParser::new(directive_line)
+        .parse_directive_ordered()
+        .map_err(|e| e.with_line(case.line_number))?;
+    if opts.sort_args {
+        args.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    let mut s = directive;
+    for (name, vals) in args {
+        s.push(' ');
+        s.push_str(&name);
+        match vals.len() {
+            0 => {}
+            1 => {
+                s.push('=');
+                s.push_str(&vals[0]);
+            }
+            _ => {
+                s.push_str("=(");
+                s.push_str(&vals.join(","));
+                s.push(')');
+            }
+        }
+    }
+    let comment = comment.trim_end();
+    if !comment.is_empty() {
+        s.push(' ');
+        s.push_str(comment);
+    }
+    Ok(s)
+}
+
+// Whether two parses of a file have the same test cases and comments in the same order. Blank
+// lines don't count, since formatting collapses them.
+fn same_meaning(a: &[Stanza], b: &[Stanza]) -> bool {
+    fn meaningful(stanzas: &[Stanza]) -> Vec<&Stanza> {
+        stanzas
+            .iter()
+            .filter(|s| !matches!(s, Stanza::Comment(c) if c.trim().is_empty()))
+            .collect()
+    }
+    let (a, b) = (meaningful(a), meaningful(b));
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|pair| match pair {
+            (Stanza::Test(a), Stanza::Test(b)) => {
+                a.directive == b.directive
+                    && a.args == b.args
+                    && a.input == b.input
+                    && a.expected == b.expected
+            }
+            (Stanza::Comment(a), Stanza::Comment(b)) => a.trim_end() == b.trim_end(),
+            _ => false,
+        })
+}
+
+fn cases(stanzas: &[Stanza]) -> Vec<&TestCase> {
+    stanzas
+        .iter()
+        .filter_map(|s| match s {
+            Stanza::Test(case) => Some(case),
+            Stanza::Comment(_) => None,
+        })
+        .collect()
+}
@@ -0,0 +1,750 @@
+//! A test harness for `harness = false` test targets, as an alternative to `#[datadriven::test]`.
+//! Each test file is its own test, and the harness understands the usual libtest command line
+//! and prints libtest-style output, so `cargo test` filters, IDEs, `cargo nextest` and CI all work
+//! the way they would with ordinary tests.
+//!
+//! ```toml
+//! [[test]]
+//! name = "datadriven"
+//! harness = false
+//! ```
+//!
+//! ```no_run
+//! // tests/datadriven.rs
+//! use datadriven::harness::{self, Harness};
+//!
+//! fn main() {
+//!     harness::main(
+//!         Harness::new().dir("tests/testdata", |f| f.run(|case| case.input.clone())),
+//!     );
+//! }
+//! ```
+//!
+//! Supported options are name filters, `--exact`, `--skip`, `--list`, `--nocapture`,
+//! `--show-output`, `--test-threads` and `--format pretty|terse|json`.
+//!
+//! Like libtest, the harness captures the output of each test, and only shows it for tests that
+//! fail, unless `--nocapture` or `--show-output` says otherwise. Unlike libtest, it can only
+//! capture what datadriven prints, like the message of a test case that panicked: there's no
+//! stable way to capture what a handler prints itself, so that always goes straight to the
+//! terminal.
+
+use std::env;
+use std::io::{self, Write};
+use std::panic;
+use std::process::exit;
+use std::sync::mpsc;
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::walk::{run_file, unreadable};
+use crate::{capture, panic_message};
+use crate::{FileReport, TestFile, Walk};
+
+type Handler = Box<dyn Fn(&mut TestFile) + Sync>;
+
+/// The test files a harness runs, and what to run them with.
+#[derive(Default)]
+pub struct Harness {
+    suites: Vec<(Walk<'static>, Handler)>,
+}
+
+impl Harness {
+    /// Creates a harness with no tests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs each test file in `dir` with `handler`.
+    pub fn dir<F>(self, dir: &str, handler: F) -> Self
+    where
+        F: Fn(&mut TestFile) + Sync + 'static,
+    {
+        self.walk(Walk::new(dir), handler)
+    }
+
+    /// Runs each test file in `walk` with `handler`, so that exclusions, lints and the rest of the
+    /// walk's configuration apply.
+    pub fn walk<F>(mut self, walk: Walk<'static>, handler: F) -> Self
+    where
+        F: Fn(&mut TestFile) + Sync + 'static,
+    {
+        self.suites.push((walk, Box::new(handler)));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Pretty,
+    Terse,
+    Json,
+}
+
+// The libtest options the harness understands.
+struct Options {
+    filters: Vec<String>,
+    skip: Vec<String>,
+    exact: bool,
+    list: bool,
+    ignored: bool,
+    nocapture: bool,
+    show_output: bool,
+    test_threads: usize,
+    format: Format,
+}
+
+impl Options {
+    fn parse<I>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut opts = Options {
+            filters: Vec::new(),
+            skip: Vec::new(),
+            exact: false,
+            list: false,
+            ignored: false,
+            nocapture: env::var_os("RUST_TEST_NOCAPTURE").is_some_and(|v| v != "0"),
+            show_output: false,
+            test_threads: default_test_threads(),
+            format: Format::Pretty,
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| match inline {
+                Some(value) => Ok(value.to_string()),
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("argument to {} is missing", name)),
+            };
+            match flag.as_str() {
+                "--exact" => opts.exact = true,
+                "--list" => opts.list = true,
+                "--ignored" => opts.ignored = true,
+                "-q" | "--quiet" => opts.format = Format::Terse,
+                "--skip" => opts.skip.push(value("--skip")?),
+                "--test-threads" => {
+                    let threads = value("--test-threads")?;
+                    opts.test_threads = match threads.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid --test-threads: {}", threads)),
+                    };
+                }
+                "--format" => {
+                    opts.format = match value("--format")?.as_str() {
+                        "pretty" => Format::Pretty,
+                        "terse" => Format::Terse,
+                        "json" => Format::Json,
+                        other => return Err(format!("unknown --format: {}", other)),
+                    }
+                }
+                "--nocapture" => opts.nocapture = true,
+                "--show-output" => opts.show_output = true,
+                // There are no ignored tests, so this runs the same tests as usual.
+                "--include-ignored" => {}
+                "--color" => {
+                    value("--color")?;
+                }
+                "-Z" => {
+                    value("-Z")?;
+                }
+                flag if flag.starts_with('-') => {
+                    return Err(format!("unrecognized option: {}", flag))
+                }
+                _ => opts.filters.push(arg),
+            }
+        }
+        Ok(opts)
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        let matches = |filter: &String| {
+            if self.exact {
+                name == filter
+            } else {
+                name.contains(filter.as_str())
+            }
+        };
+        (self.filters.is_empty() || self.filters.iter().any(matches))
+            && !self.skip.iter().any(matches)
+    }
+}
+
+fn default_test_threads() -> usize {
+    match env::var("RUST_TEST_THREADS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+    {
+        Some(n) if n > 0 => n,
+        _ => thread::available_parallelism().map_or(1, |n| n.get()),
+    }
+}
+
+/// Runs the harness with the command line arguments of the process, and exits with a nonzero
+/// status if any tests failed.
+pub fn main(harness: Harness) {
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            exit(101);
+        }
+    };
+    match run(harness, &opts, io::stdout()) {
+        Ok(true) => {}
+        Ok(false) => exit(101),
+        Err(err) => {
+            eprintln!("error: writing test output: {}", err);
+            exit(101);
+        }
+    }
+}
+
+// Runs the selected tests, writing what happened to `out` and returning whether they all passed.
+fn run<W: Write>(harness: Harness, opts: &Options, out: W) -> io::Result<bool> {
+    let mut tests = Vec::new();
+    let mut filtered_out = 0;
+    for (suite, (walk, handler)) in harness.suites.iter().enumerate() {
+        let files = match walk.files() {
+            Ok(files) => files,
+            // A walk that can't find its files at all fails as a test named after its directory.
+            Err(err) => vec![Err(walk.abandoned_file(err))],
+        };
+        for tf in files {
+            let name = match &tf {
+                Ok(tf) => tf.filename.clone(),
+                Err(file) => file.filename.clone(),
+            };
+            // There are no ignored tests, so `--ignored` runs nothing.
+            if opts.selects(&name) && !opts.ignored {
+                tests.push((suite, name, tf, handler));
+            } else {
+                filtered_out += 1;
+            }
+        }
+    }
+
+    let mut out = Output::new(opts, out);
+    if opts.list {
+        let names: Vec<&str> = tests.iter().map(|(_, name, _, _)| name.as_str()).collect();
+        out.list(&names)?;
+        return Ok(true);
+    }
+
+    let capture = !opts.nocapture;
+    if capture {
+        capture_panics();
+    }
+    out.suite_started(tests.len())?;
+    let started = Instant::now();
+    let names: Vec<String> = tests.iter().map(|(_, name, _, _)| name.clone()).collect();
+    let suites: Vec<usize> = tests.iter().map(|(suite, _, _, _)| *suite).collect();
+    // What happened in each suite, for the walk it came from to report on.
+    let mut reports = vec![Vec::new(); harness.suites.len()];
+    let queue = Mutex::new(tests.into_iter().enumerate());
+    let reporters: Vec<_> = harness
+        .suites
+        .iter()
+        .map(|(walk, _)| walk.reporters())
+        .collect();
+    let (tx, rx) = mpsc::channel();
+    let mut results = Vec::new();
+    thread::scope(|s| -> io::Result<()> {
+        for _ in 0..opts.test_threads.min(names.len()) {
+            let tx = tx.clone();
+            let queue = &queue;
+            let reporters = &reporters;
+            s.spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let (idx, (suite, _, tf, handler)) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let _ = tx.send(Event::Started(idx));
+                if capture {
+                    capture::start();
+                }
+                let file = match tf {
+                    Ok(tf) => run_file(tf, handler),
+                    Err(file) => unreadable(file, reporters[suite]),
+                };
+                let _ = tx.send(Event::Finished(idx, file, capture::finish()));
+            });
+        }
+        drop(tx);
+        for event in rx {
+            match event {
+                Event::Started(idx) => out.test_started(&names[idx])?,
+                Event::Finished(idx, file, output) => {
+                    out.test_finished(&names[idx], &file, &output)?;
+                    reports[suites[idx]].push((idx, file.clone()));
+                    results.push((idx, file, output));
+                }
+            }
+        }
+        Ok(())
+    })?;
+    for ((walk, _), mut files) in harness.suites.iter().zip(reports) {
+        // Suites that were entirely filtered out didn't run, rather than running nothing.
+        if files.is_empty() {
+            continue;
+        }
+        files.sort_by_key(|(idx, _)| *idx);
+        walk.done(files.into_iter().map(|(_, file)| file).collect(), started);
+    }
+    results.sort_by_key(|(idx, _, _)| *idx);
+    let results: Vec<_> = results
+        .into_iter()
+        .map(|(idx, file, output)| Finished {
+            name: &names[idx],
+            file,
+            output,
+        })
+        .collect();
+    out.suite_finished(&results, filtered_out, started.elapsed())?;
+    Ok(results.iter().all(Finished::passed))
+}
+
+// Sends panic messages from threads that are capturing output to what they capture, rather than
+// stderr.
+fn capture_panics() {
+    static HOOKED: Once = Once::new();
+    HOOKED.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let thread = thread::current();
+            let location = info
+                .location()
+                .map_or_else(String::new, |l| format!(" at {}", l));
+            if !capture::captured(format_args!(
+                "thread '{}' panicked{}:\n{}\n",
+                thread.name().unwrap_or("<unnamed>"),
+                location,
+                panic_message(info.payload())
+            )) {
+                hook(info);
+            }
+        }));
+    });
+}
+
+enum Event {
+    Started(usize),
+    Finished(usize, FileReport, String),
+}
+
+// A test that has run, with what it printed.
+struct Finished<'a> {
+    name: &'a str,
+    file: FileReport,
+    output: String,
+}
+
+impl Finished<'_> {
+    fn passed(&self) -> bool {
+        self.file.failures.is_empty()
+    }
+}
+
+// Prints results the way libtest would.
+struct Output<W> {
+    out: W,
+    format: Format,
+    show_output: bool,
+    // How many results have been printed on the current line in the terse format.
+    terse_column: usize,
+}
+
+impl<W: Write> Output<W> {
+    fn new(opts: &Options, out: W) -> Self {
+        Output {
+            out,
+            format: opts.format,
+            show_output: opts.show_output,
+            terse_column: 0,
+        }
+    }
+
+    fn list(&mut self, names: &[&str]) -> io::Result<()> {
+        if self.format == Format::Json {
+            writeln!(self.out, r#"{{ "type": "suite", "event": "discovery" }}"#)?;
+            for name in names {
+                // The whole file is the test, so it's where the test is too.
+                writeln!(
+                    self.out,
+                    r#"{{ "type": "test", "event": "discovered", "name": {}, "ignore": false, "ignore_message": "", "source_path": {}, "start_line": 1, "start_col": 1, "end_line": 1, "end_col": 1 }}"#,
+                    json_string(name),
+                    json_string(name)
+                )?;
+            }
+            return writeln!(
+                self.out,
+                r#"{{ "type": "suite", "event": "completed", "tests": {}, "benchmarks": 0, "total": {}, "ignored": 0 }}"#,
+                names.len(),
+                names.len()
+            );
+        }
+        for name in names {
+            writeln!(self.out, "{}: test", name)?;
+        }
+        if self.format == Format::Pretty {
+            writeln!(self.out)?;
+            writeln!(self.out, "{} tests, 0 benchmarks", names.len())?;
+        }
+        Ok(())
+    }
+
+    fn suite_started(&mut self, count: usize) -> io::Result<()> {
+        match self.format {
+            Format::Json => writeln!(
+                self.out,
+                r#"{{ "type": "suite", "event": "started", "test_count": {} }}"#,
+                count
+            ),
+            _ => {
+                writeln!(self.out)?;
+                writeln!(self.out, "running {} test{}", count, plural(count))
+            }
+        }
+    }
+
+    fn test_started(&mut self, name: &str) -> io::Result<()> {
+        if self.format == Format::Json {
+            writeln!(
+                self.out,
+                r#"{{ "type": "test", "event": "started", "name": {} }}"#,
+                json_string(name)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn test_finished(&mut self, name: &str, file: &FileReport, output: &str) -> io::Result<()> {
+        let passed = file.failures.is_empty();
+        match self.format {
+            Format::Pretty => writeln!(
+                self.out,
+                "test {} ... {}",
+                name,
+                if passed { "ok" } else { "FAILED" }
+            ),
+            Format::Terse => {
+                write!(self.out, "{}", if passed { "." } else { "F" })?;
+                self.terse_column += 1;
+                if self.terse_column == 88 {
+                    writeln!(self.out)?;
+                    self.terse_column = 0;
+                }
+                Ok(())
+            }
+            Format::Json if passed => {
+                write!(
+                    self.out,
+                    r#"{{ "type": "test", "name": {}, "event": "ok", "exec_time": {}"#,
+                    json_string(name),
+                    file.duration.as_secs_f64()
+                )?;
+                if self.show_output && !output.is_empty() {
+                    write!(self.out, r#", "stdout": {}"#, json_string(output))?;
+                }
+                writeln!(self.out, " }}")
+            }
+            Format::Json => writeln!(
+                self.out,
+                r#"{{ "type": "test", "name": {}, "event": "failed", "exec_time": {}, "stdout": {} }}"#,
+                json_string(name),
+                file.duration.as_secs_f64(),
+                json_string(&failure_output(file, output))
+            ),
+        }
+    }
+
+    fn suite_finished(
+        &mut self,
+        results: &[Finished<'_>],
+        filtered_out: usize,
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        let (passed, failed): (Vec<_>, Vec<_>) = results.iter().partition(|r| r.passed());
+        let ok = failed.is_empty();
+        if self.format == Format::Json {
+            return writeln!(
+                self.out,
+                r#"{{ "type": "suite", "event": "{}", "passed": {}, "failed": {}, "ignored": 0, "measured": 0, "filtered_out": {}, "exec_time": {} }}"#,
+                if ok { "ok" } else { "failed" },
+                passed.len(),
+                failed.len(),
+                filtered_out,
+                elapsed.as_secs_f64()
+            );
+        }
+        if self.terse_column > 0 {
+            writeln!(self.out)?;
+        }
+        if self.show_output {
+            writeln!(self.out)?;
+            writeln!(self.out, "successes:")?;
+            writeln!(self.out)?;
+            for result in passed.iter().filter(|r| !r.output.is_empty()) {
+                writeln!(self.out, "---- {} stdout ----", result.name)?;
+                write!(self.out, "{}", result.output)?;
+                writeln!(self.out)?;
+            }
+            writeln!(self.out)?;
+            writeln!(self.out, "successes:")?;
+            for result in &passed {
+                writeln!(self.out, "    {}", result.name)?;
+            }
+        }
+        if !ok {
+            writeln!(self.out)?;
+            writeln!(self.out, "failures:")?;
+            writeln!(self.out)?;
+            for result in &failed {
+                writeln!(self.out, "---- {} stdout ----", result.name)?;
+                writeln!(self.out, "{}", failure_output(&result.file, &result.output))?;
+            }
+            writeln!(self.out)?;
+            writeln!(self.out, "failures:")?;
+            for result in &failed {
+                writeln!(self.out, "    {}", result.name)?;
+            }
+        }
+        writeln!(self.out)?;
+        writeln!(
+            self.out,
+            "test result: {}. {} passed; {} failed; 0 ignored; 0 measured; {} filtered out; \
+             finished in {:.2}s",
+            if ok { "ok" } else { "FAILED" },
+            passed.len(),
+            failed.len(),
+            filtered_out,
+            elapsed.as_secs_f64()
+        )?;
+        writeln!(self.out)
+    }
+}
+
+// What a failed test printed, followed by its failures.
+fn failure_output(file: &FileReport, output: &str) -> String {
+    let mut msg = output.to_string();
+    for failure in &file.failures {
+        msg.push_str(failure);
+        msg.push('\n');
+    }
+    msg
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+// Quotes and escapes a string for JSON.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::{run, Format, Harness, Options};
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    // Runs the macro test files, where `simple` fails by panicking, and returns whether everything
+    // passed along with the output, with timings blanked out.
+    fn harness_output(args: &[&str]) -> (bool, String) {
+        let harness = Harness::new().dir("tests/testdata_macro", |f| {
+            f.run(|case| {
+                if case.input == "hello\n" {
+                    panic!("no greetings");
+                }
+                case.input.clone()
+            })
+        });
+        let mut out = Vec::new();
+        let mut opts = parse(args).unwrap();
+        opts.test_threads = 1;
+        let passed = run(harness, &opts, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let out = Regex::new(r"\d+\.\d+(e-\d+)?(s\b)?")
+            .unwrap()
+            .replace_all(&out, "T");
+        let out = Regex::new(r"src/harness.rs:\d+:\d+")
+            .unwrap()
+            .replace_all(&out, "HERE");
+        (passed, out.into_owned())
+    }
+
+    #[test]
+    fn options() {
+        let opts = parse(&["nested", "--exact", "--test-threads=3", "--format", "json"]).unwrap();
+        assert_eq!(opts.filters, vec!["nested"]);
+        assert!(opts.exact);
+        assert_eq!(opts.test_threads, 3);
+        assert_eq!(opts.format, Format::Json);
+        assert!(opts.selects("nested"));
+        assert!(!opts.selects("tests/nested"));
+
+        let opts = parse(&["nested", "simple", "--skip", "more", "--nocapture"]).unwrap();
+        assert!(opts.nocapture);
+        assert!(opts.selects("tests/testdata_macro/simple"));
+        assert!(!opts.selects("tests/testdata_macro/nested/more-cases"));
+        assert!(!opts.selects("tests/testdata_async_state/sum"));
+
+        assert!(parse(&["--test-threads", "0"]).is_err());
+        assert!(parse(&["--format"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn pretty() {
+        let (passed, out) = harness_output(&[]);
+        assert!(!passed);
+        assert_eq!(
+            out,
+            [
+                "",
+                "running 2 tests",
+                "test tests/testdata_macro/nested/more-cases ... ok",
+                "test tests/testdata_macro/simple ... FAILED",
+                "",
+                "failures:",
+                "",
+                "---- tests/testdata_macro/simple stdout ----",
+                "thread '<unnamed>' panicked at HERE:",
+                "no greetings",
+                "failure:",
+                "tests/testdata_macro/simple:1:",
+                "hello",
+                "",
+                "panicked: no greetings",
+                "",
+                "",
+                "",
+                "failures:",
+                "    tests/testdata_macro/simple",
+                "",
+                "test result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; \
+                 finished in T",
+                "",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn terse() {
+        // Without capturing, the panic goes to stderr, and only the failure is shown.
+        let (_, out) = harness_output(&["-q", "--nocapture", "--show-output", "simple"]);
+        assert_eq!(
+            out,
+            [
+                "",
+                "running 1 test",
+                "F",
+                "",
+                "successes:",
+                "",
+                "",
+                "successes:",
+                "",
+                "failures:",
+                "",
+                "---- tests/testdata_macro/simple stdout ----",
+                "failure:",
+                "tests/testdata_macro/simple:1:",
+                "hello",
+                "",
+                "panicked: no greetings",
+                "",
+                "",
+                "",
+                "failures:",
+                "    tests/testdata_macro/simple",
+                "",
+                "test result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 1 filtered out; \
+                 finished in T",
+                "",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn json() {
+        let (_, out) = harness_output(&["--format=json"]);
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                r#"{ "type": "suite", "event": "started", "test_count": 2 }"#,
+                r#"{ "type": "test", "event": "started", "name": "tests/testdata_macro/nested/more-cases" }"#,
+                r#"{ "type": "test", "name": "tests/testdata_macro/nested/more-cases", "event": "ok", "exec_time": T }"#,
+                r#"{ "type": "test", "event": "started", "name": "tests/testdata_macro/simple" }"#,
+                r#"{ "type": "test", "name": "tests/testdata_macro/simple", "event": "failed", "exec_time": T, "stdout": "thread '<unnamed>' panicked at HERE:\nno greetings\nfailure:\ntests/testdata_macro/simple:1:\nhello\n\npanicked: no greetings\n\n" }"#,
+                r#"{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 0, "measured": 0, "filtered_out": 0, "exec_time": T }"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn list() {
+        let (passed, out) = harness_output(&["--list"]);
+        assert!(passed);
+        assert_eq!(
+            out,
+            "tests/testdata_macro/nested/more-cases: test\n\
+             tests/testdata_macro/simple: test\n\
+             \n\
+             2 tests, 0 benchmarks\n"
+        );
+
+        let (_, out) = harness_output(&["--list", "--format", "terse", "--skip", "nested"]);
+        assert_eq!(out, "tests/testdata_macro/simple: test\n");
+
+        let (_, out) = harness_output(&[
+            "--list",
+            "--format",
+            "json",
+            "--exact",
+            "tests/testdata_macro/simple",
+        ]);
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                r#"{ "type": "suite", "event": "discovery" }"#,
+                r#"{ "type": "test", "event": "discovered", "name": "tests/testdata_macro/simple", "ignore": false, "ignore_message": "", "source_path": "tests/testdata_macro/simple", "start_line": 1, "start_col": 1, "end_line": 1, "end_col": 1 }"#,
+                r#"{ "type": "suite", "event": "completed", "tests": 1, "benchmarks": 0, "total": 1, "ignored": 0 }"#,
+            ]
+        );
+    }
+}
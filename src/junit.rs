@@ -0,0 +1,268 @@
+//! Writing reports as JUnit XML, for CI systems that ingest it. See `Walk::junit`.
+//!
+//! Each test file is a `<testsuite>`, and each test case a `<testcase>` named `directive@line`.
+//! There is no way to mark a test case as expected to fail, so nothing is ever reported as xfail.
+
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::{capture, diff, write_atomic, FileReport, Outcome, Report, Reporter};
+
+// A `<testsuite>`, either read back from an existing report or rendered from a file.
+struct Suite {
+    name: String,
+    counts: Counts,
+    time: Duration,
+    xml: String,
+}
+
+/// Writes a JUnit report to a path at the end of each walk.
+pub(crate) struct JUnit(pub(crate) PathBuf);
+
+impl Reporter for JUnit {
+    fn on_finish(&self, report: &Report) {
+        if let Err(err) = record(&self.0, report) {
+            capture::eprintln(format_args!(
+                "warning: couldn't write JUnit report to {}: {}",
+                self.0.display(),
+                err
+            ));
+        }
+    }
+}
+
+// Adds the files in `report` to the JUnit report at `path`, replacing the suites of any files it
+// already has. Other walks, in this process or in the other test binaries `cargo test` runs, can
+// be writing to the same report, so the report is read and written while holding a lock on a
+// `.lock` file next to it.
+fn record(path: &Path, report: &Report) -> io::Result<()> {
+    let mut lock_path = OsString::from(path);
+    lock_path.push(".lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(lock_path))?;
+    // Unlocked when it is closed.
+    lock.lock()?;
+
+    let mut suites = match fs::read_to_string(path) {
+        Ok(xml) => parse_suites(&xml),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    for file in &report.files {
+        let suite = Suite::of(file);
+        match suites.iter_mut().find(|s| s.name == suite.name) {
+            Some(existing) => *existing = suite,
+            None => suites.push(suite),
+        }
+    }
+    write_atomic(path, &render(&suites))
+}
+
+impl Suite {
+    fn of(file: &FileReport) -> Self {
+        let counts = Counts::of(file);
+        let mut xml = String::new();
+        render_suite(&mut xml, file, &counts);
+        Suite {
+            name: escape(&file.filename),
+            counts,
+            time: file.duration,
+            xml,
+        }
+    }
+}
+
+fn render(suites: &[Suite]) -> String {
+    let mut totals = Counts::default();
+    let mut time = Duration::ZERO;
+    for suite in suites {
+        totals.add(&suite.counts);
+        time += suite.time;
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<testsuites name=\"datadriven\" {} time=\"{}\">",
+        totals.attrs(),
+        seconds(time)
+    )
+    .unwrap();
+    for suite in suites {
+        out.push_str(&suite.xml);
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+fn render_suite(out: &mut String, file: &FileReport, counts: &Counts) {
+    let name = escape(&file.filename);
+    writeln!(
+        out,
+        "  <testsuite name=\"{}\" {} time=\"{}\">",
+        name,
+        counts.attrs(),
+        seconds(file.duration)
+    )
+    .unwrap();
+    for case in &file.cases {
+        write!(
+            out,
+            "    <testcase name=\"{}\" classname=\"{}\" file=\"{}\" line=\"{}\" time=\"{}\"",
+            escape(&format!("{}@{}", case.directive, case.line)),
+            name,
+            name,
+            case.line,
+            seconds(case.duration)
+        )
+        .unwrap();
+        match &case.outcome {
+            // A rewritten test case expects what it produced now.
+            Outcome::Passed | Outcome::Rewritten(_) => out.push_str("/>\n"),
+            Outcome::Skipped => out.push_str(">\n      <skipped/>\n    </testcase>\n"),
+            Outcome::Failed(actual) => {
+                let diff = diff::unified_diff(&case.expected, actual, case.expected_line, false);
+                writeln!(
+                    out,
+                    ">\n      <failure message=\"output didn't match\">{}</failure>\n    </testcase>",
+                    escape(&with_input(&case.input, &diff))
+                )
+                .unwrap();
+            }
+            Outcome::Errored(err) => {
+                writeln!(
+                    out,
+                    ">\n      <error message=\"{}\">{}</error>\n    </testcase>",
+                    escape(err.lines().next().unwrap_or_default()),
+                    escape(&with_input(&case.input, err))
+                )
+                .unwrap();
+            }
+        }
+    }
+    // Failures that don't belong to a test case, like lint failures or a panic outside of a test
+    // case, still need to show up as a failing test.
+    if counts.file_failed {
+        writeln!(
+            out,
+            "    <testcase name=\"(file)\" classname=\"{}\" file=\"{}\" time=\"0.000\">\n      \
+             <failure message=\"the file failed\">{}</failure>\n    </testcase>",
+            name,
+            name,
+            escape(&file.failures.join("\n"))
+        )
+        .unwrap();
+    }
+    out.push_str("  </testsuite>\n");
+}
+
+// The suites in a report this module wrote, as they were written.
+fn parse_suites(xml: &str) -> Vec<Suite> {
+    let re = Regex::new(
+        r#"(?s)  <testsuite name="([^"]*)" tests="(\d+)" failures="(\d+)" errors="(\d+)" skipped="(\d+)" time="([\d.]+)">\n.*?  </testsuite>\n"#,
+    )
+    .unwrap();
+    re.captures_iter(xml)
+        .map(|c| {
+            let n = |i: usize| -> usize { c[i].parse().unwrap_or_default() };
+            Suite {
+                name: c[1].to_string(),
+                counts: Counts {
+                    tests: n(2),
+                    failures: n(3),
+                    errors: n(4),
+                    skipped: n(5),
+                    file_failed: false,
+                },
+                time: Duration::from_secs_f64(c[6].parse().unwrap_or_default()),
+                xml: c[0].to_string(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Counts {
+    tests: usize,
+    failures: usize,
+    errors: usize,
+    skipped: usize,
+    // Whether the file failed without any of its test cases failing.
+    file_failed: bool,
+}
+
+impl Counts {
+    fn of(file: &FileReport) -> Self {
+        let mut counts = Counts::default();
+        for case in &file.cases {
+            counts.tests += 1;
+            match case.outcome {
+                Outcome::Passed | Outcome::Rewritten(_) => {}
+                Outcome::Failed(_) => counts.failures += 1,
+                Outcome::Errored(_) => counts.errors += 1,
+                Outcome::Skipped => counts.skipped += 1,
+            }
+        }
+        if !file.failures.is_empty() && counts.failures + counts.errors == 0 {
+            counts.file_failed = true;
+            counts.tests += 1;
+            counts.failures += 1;
+        }
+        counts
+    }
+
+    fn add(&mut self, other: &Counts) {
+        self.tests += other.tests;
+        self.failures += other.failures;
+        self.errors += other.errors;
+        self.skipped += other.skipped;
+    }
+
+    fn attrs(&self) -> String {
+        format!(
+            "tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\"",
+            self.tests, self.failures, self.errors, self.skipped
+        )
+    }
+}
+
+// What went wrong, after the input that caused it, if there was any.
+fn with_input(input: &str, details: &str) -> String {
+    if input.is_empty() {
+        details.to_string()
+    } else {
+        format!("{}\n{}", input, details)
+    }
+}
+
+fn seconds(d: Duration) -> String {
+    format!("{:.3}", d.as_secs_f64())
+}
+
+// Escapes text for use in an attribute or element. Characters XML doesn't allow at all, like the
+// escape codes in colored output, are replaced.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c < ' ' => out.push(char::REPLACEMENT_CHARACTER),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -1,22 +1,78 @@
+use std::any::Any;
+use std::cmp::Ordering;
 use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::fmt::Write;
 use std::fs;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::result::Result;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
+mod capture;
+mod diff;
+mod events;
+mod filter;
+pub mod fmt;
+pub mod harness;
+mod junit;
+pub mod lint;
+mod report;
+mod reporter;
+mod timeout;
+mod timing;
+mod walk;
+
+pub use report::{CaseResult, FileReport, Outcome, Report, Summary};
+pub use reporter::{PanicOnFailure, Reporter};
+
+/// Generates a separate `#[test]` for each test file under a directory, so that `cargo test`
+/// can filter and parallelize by file. The function it's attached to is run on each file:
+///
+/// ```ignore
+/// #[datadriven::test("tests/testdata")]
+/// fn run(f: &mut datadriven::TestFile) {
+///     f.run(|case| case.input.clone());
+/// }
+/// ```
+///
+/// The tests go in a module with the same name as the function, and are named after each file's
+/// path relative to the directory, like `run::nested::nested_file`. Characters that can't be in an
+/// identifier are replaced with `_`. The directory is relative to the crate root.
+///
+/// Files are found when the test is compiled. Edits to them trigger a rebuild, but on a stable
+/// compiler, new files don't, unless a build script asks for it:
+///
+/// ```ignore
+/// // build.rs
+/// fn main() {
+///     println!("cargo:rerun-if-changed=tests/testdata");
+/// }
+/// ```
+///
+/// On a nightly compiler, the `nightly` feature tracks the directories themselves, so new files
+/// trigger a rebuild without one. On a stable compiler the feature does nothing, so it can be left
+/// on under `--all-features`.
+#[cfg(feature = "macros")]
+pub use datadriven_macros::test;
+pub use walk::{LintLevel, Walk};
+
+use filter::{CaseFilter, Selection};
+use reporter::Reporters;
+
 #[cfg(feature = "async")]
-use futures::future::Future;
+use futures::future::{BoxFuture, Future, FutureExt};
 
 #[derive(Error, Debug)]
 pub enum DataDrivenError {
     #[error("parsing: {0}")]
     Parse(String),
-    #[error("reading files: {0}")]
+    #[error("io: {0}")]
     Io(std::io::Error),
-    #[error("{filename}:{line}: {inner}")]
+    #[error("{}: {inner}", location(.filename, .line))]
     WithContext {
         line: usize,
         filename: String,
@@ -28,6 +84,15 @@ pub enum DataDrivenError {
     DidntUseAllArguments(Vec<String>),
 }
 
+// Errors about a whole file don't have a line number.
+fn location(filename: &str, line: &usize) -> String {
+    if *line == 0 {
+        filename.to_string()
+    } else {
+        format!("{}:{}", filename, line)
+    }
+}
+
 impl DataDrivenError {
     fn with_line(self, line: usize) -> Self {
         match self {
@@ -107,6 +172,32 @@ pub struct TestCase {
     directive_line: String,
     expected: String,
     line_number: usize,
+    layout: Layout,
+    // Taken from the `timeout` argument, if it is a duration.
+    timeout: Option<Duration>,
+}
+
+// Where the parts of a test case were found in its file, for tools that need to point back at
+// them.
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    // The line of the `----` separating the input from the expected output, if there was one.
+    separator_line: Option<usize>,
+    // Whether the expected output is enclosed in double separators.
+    double_separator: bool,
+    // A non-blank line directly following a double-separator block, which the parser skips.
+    stray_line: Option<usize>,
+    // The bytes making up the expected output block, separators included. For a test case with
+    // no separator, this is the empty range at the end of the file.
+    expected_range: Range<usize>,
+}
+
+impl Layout {
+    // The line the expected output starts on.
+    fn expected_line(&self) -> Option<usize> {
+        let skip = if self.double_separator { 2 } else { 1 };
+        self.separator_line.map(|line| line + skip)
+    }
 }
 
 impl TestCase {
@@ -223,6 +314,11 @@ impl TestCase {
             ))
         }
     }
+
+    /// The line of the file the test case starts on, which is where its directive is.
+    pub fn line(&self) -> usize {
+        self.line_number
+    }
 }
 
 /// Walk a directory for test files and run each one as a test.
@@ -230,51 +326,36 @@ pub fn walk<F>(dir: &str, f: F)
 where
     F: FnMut(&mut TestFile),
 {
-    walk_exclusive(dir, f, |_| false);
+    Walk::new(dir).run(f);
+}
+
+/// The same as `walk`, but returns a report of what happened rather than panicking if anything
+/// failed.
+pub fn try_walk<F>(dir: &str, f: F) -> Report
+where
+    F: FnMut(&mut TestFile),
+{
+    Walk::new(dir).try_run(f)
 }
 
 /// The same as `walk` but accepts an additional matcher to exclude matching files from being
 /// tested.
-pub fn walk_exclusive<F, M>(dir: &str, mut f: F, exclusion_matcher: M)
+pub fn walk_exclusive<F, M>(dir: &str, f: F, exclusion_matcher: M)
 where
     F: FnMut(&mut TestFile),
     M: Fn(&TestFile) -> bool,
 {
-    let mut file_prefix = PathBuf::from(dir);
-    if let Ok(p) = env::var("RUN") {
-        file_prefix = file_prefix.join(p);
-    }
-
-    // Accumulate failures until the end since Rust doesn't let us "fail but keep going" in a test.
-    let mut failures = Vec::new();
-
-    let mut run = |file| {
-        let mut tf = TestFile::new(&file).unwrap();
-        if exclusion_matcher(&tf) {
-            return;
-        }
-        f(&mut tf);
-        if let Some(fail) = tf.failure {
-            failures.push(fail);
-        }
-    };
-
-    if file_prefix.is_dir() {
-        for file in test_files(PathBuf::from(dir)).unwrap() {
-            run(file);
-        }
-    } else if file_prefix.exists() {
-        run(file_prefix);
-    }
+    Walk::new(dir).exclude(exclusion_matcher).run(f);
+}
 
-    if !failures.is_empty() {
-        let mut msg = String::new();
-        for f in failures {
-            msg.push_str(&f);
-            msg.push('\n');
-        }
-        panic!("{}", msg);
-    }
+/// The same as `walk` but runs files on up to `threads` threads at once. `make_handler` is called
+/// to make a fresh handler for each file.
+pub fn walk_parallel<M, H>(dir: &str, threads: usize, make_handler: M)
+where
+    M: Fn() -> H + Sync,
+    H: FnMut(&mut TestFile),
+{
+    Walk::new(dir).run_parallel(threads, make_handler);
 }
 
 // Ignore files named .XXX, XXX~ or #XXX#.
@@ -282,7 +363,8 @@ fn should_ignore_file(name: &str) -> bool {
     name.starts_with('.') || name.ends_with('~') || name.starts_with('#') && name.ends_with('#')
 }
 
-// Extracts all the non-directory children of dir. Not defensive against cycles!
+// Extracts all the non-directory children of dir, in natural order (see `natural_cmp`), so that
+// walks run files in the same order everywhere. Not defensive against cycles!
 fn test_files(dir: PathBuf) -> Result<Vec<PathBuf>, DataDrivenError> {
     let mut q = VecDeque::new();
     q.push_back(dir);
@@ -297,9 +379,60 @@ fn test_files(dir: PathBuf) -> Result<Vec<PathBuf>, DataDrivenError> {
             }
         }
     }
+    res.sort_by(|a, b| {
+        let components = |p: &Path| -> Vec<String> {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect()
+        };
+        let (a, b) = (components(a), components(b));
+        a.iter()
+            .zip(&b)
+            .map(|(a, b)| natural_cmp(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    });
     Ok(res)
 }
 
+// Compares strings the way a person would order them, with runs of digits compared as numbers, so
+// that `file2` comes before `file10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    // Splits off the leading run of digits or non-digits.
+    fn chunk(s: &str) -> (&str, &str) {
+        let digits = s.starts_with(|c: char| c.is_ascii_digit());
+        let end = s
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(s.len());
+        s.split_at(end)
+    }
+
+    let (mut a, mut b) = (a, b);
+    while !a.is_empty() && !b.is_empty() {
+        let ((x, rest_a), (y, rest_b)) = (chunk(a), chunk(b));
+        let numbers = x.starts_with(|c: char| c.is_ascii_digit())
+            && y.starts_with(|c: char| c.is_ascii_digit());
+        let order = if numbers {
+            let (xs, ys) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            // Equal numbers with more leading zeros go first, so that the order is still total.
+            xs.len()
+                .cmp(&ys.len())
+                .then_with(|| xs.cmp(ys))
+                .then_with(|| y.len().cmp(&x.len()))
+        } else {
+            x.cmp(y)
+        };
+        if order.is_ne() {
+            return order;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+    a.len().cmp(&b.len())
+}
+
+// An argument on a directive line, along with its values.
+type DirectiveArg = (String, Vec<String>);
+
 /// Parses a directive line of the form
 /// <directive> {arg={<value>|(<value>[,<value>]*)}}*
 /// Examples:
@@ -352,7 +485,7 @@ impl DirectiveParser {
 
     fn parse_word(&mut self, context: &str) -> Result<String, DataDrivenError> {
         let start = self.idx;
-        while self.peek().map_or(false, Self::is_wordchar) {
+        while self.peek().is_some_and(Self::is_wordchar) {
             self.idx += 1;
         }
         if self.idx == start {
@@ -419,18 +552,24 @@ impl DirectiveParser {
     fn parse_directive(
         &mut self,
     ) -> Result<(String, HashMap<String, Vec<String>>), DataDrivenError> {
+        let (directive, args) = self.parse_directive_ordered()?;
+        Ok((directive, args.into_iter().collect()))
+    }
+
+    // Like `parse_directive`, but keeps the arguments in the order they were written.
+    fn parse_directive_ordered(&mut self) -> Result<(String, Vec<DirectiveArg>), DataDrivenError> {
         self.munch();
         let directive = self.parse_word("directive")?;
-        let mut args = HashMap::new();
+        let mut args: Vec<DirectiveArg> = Vec::new();
         while !self.at_end() {
             let (arg_name, arg_vals) = self.parse_arg()?;
-            if args.contains_key(&arg_name) {
+            if args.iter().any(|(name, _)| *name == arg_name) {
                 Err(DataDrivenError::Parse(format!(
                     "duplicate argument: {}",
                     arg_name
                 )))?;
             }
-            args.insert(arg_name, arg_vals);
+            args.push((arg_name, arg_vals));
         }
         Ok((directive, args))
     }
@@ -451,10 +590,136 @@ pub struct TestFile {
     /// The name of the file
     pub filename: String,
 
-    // failure gets set if a test failed during execution. We can't just return an error when that
-    // happens, since the user is calling `run` from a closure, so we have to buffer up a failure
-    // to be processed later (by `walk`).
-    failure: Option<String>,
+    // The contents of the file as it was read, so that rewrites can leave parts of it untouched.
+    contents: String,
+
+    // failures get recorded if a test failed during execution. We can't just return an error when
+    // that happens, since the user is calling `run` from a closure, so we have to buffer up
+    // failures to be processed later (by `walk`).
+    failures: Failures,
+
+    // What happened to each test case the last time the file was run.
+    results: Vec<CaseResult>,
+
+    // Whether the last run rewrote the file.
+    rewrote: bool,
+
+    config: Config,
+}
+
+// By default, give up on a file after this many test cases fail in a row, since later test cases
+// usually depend on the state built up by earlier ones.
+const DEFAULT_MAX_CONSECUTIVE_FAILURES: usize = 3;
+
+// Settings for running the test cases in a file. These are set up by the walk that created the
+// file.
+#[derive(Debug, Clone)]
+struct Config {
+    max_consecutive_failures: Option<usize>,
+    rewrite: Option<RewriteMode>,
+    errors_as_output: bool,
+    timeout: Option<Duration>,
+    filter: CaseFilter,
+    reporters: Reporters,
+}
+
+impl Config {
+    // A `timeout` argument on the test case takes precedence over the walk's.
+    fn timeout_for(&self, case: &TestCase) -> Option<Duration> {
+        case.timeout.or(self.timeout)
+    }
+
+    // Turns what a test case returned into its output. Errors are rendered into the output if the
+    // walk asked for that, and otherwise passed along to be reported as failures.
+    fn output<R: TestCaseResult>(&self, result: R) -> Result<String, String> {
+        match result.result() {
+            Ok(output) => Ok(output),
+            Err(err) if self.errors_as_output => Ok(format!("error: {}\n", err)),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    // Records how a test case did, once that's known.
+    fn finish_case(&self, filename: &str, result: &mut CaseResult, outcome: Outcome) {
+        result.outcome = outcome;
+        self.reporters.on_case_result(filename, result);
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_consecutive_failures: Some(DEFAULT_MAX_CONSECUTIVE_FAILURES),
+            rewrite: env::var("REWRITE").ok().map(|v| RewriteMode::from_env(&v)),
+            errors_as_output: false,
+            timeout: None,
+            filter: CaseFilter::default(),
+            reporters: Reporters::default(),
+        }
+    }
+}
+
+/// How test files get rewritten when the `REWRITE` environment variable is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewriteMode {
+    /// Re-render every test case in every file that runs. This is the default.
+    All,
+    /// Only replace the expected output of test cases whose output changed, leaving the rest of
+    /// the file byte-for-byte identical. Selected with `REWRITE=failing`.
+    Failing,
+    /// Don't write anything, but fail if rewriting would change the file. This catches files that
+    /// pass but aren't in the form a rewrite would leave them in. Selected with `REWRITE=check`.
+    Check,
+}
+
+impl RewriteMode {
+    fn from_env(value: &str) -> Self {
+        match value {
+            "failing" => RewriteMode::Failing,
+            "check" => RewriteMode::Check,
+            _ => RewriteMode::All,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Failures {
+    messages: Vec<String>,
+    consecutive: usize,
+}
+
+impl Failures {
+    fn pass(&mut self) {
+        self.consecutive = 0;
+    }
+
+    // Records a failed test case. Returns true if enough test cases have failed in a row that the
+    // rest of the file should be skipped.
+    fn fail(&mut self, message: String, max_consecutive: Option<usize>) -> bool {
+        self.messages.push(message);
+        self.consecutive += 1;
+        match max_consecutive {
+            Some(max) if self.consecutive >= max => {
+                self.messages.push(format!(
+                    "skipped the rest of the file after {} consecutive failure{}\n",
+                    self.consecutive,
+                    if self.consecutive == 1 { "" } else { "s" },
+                ));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// How running a single test case went, for the loops that run a whole file.
+enum Ran<R> {
+    // The handler returned, and the test case was selected to be compared.
+    Returned(R),
+    // The test case only ran to build up state for the ones a filter selected, and is done.
+    RunOnly,
+    // The handler panicked or timed out, and the rest of the file is skipped.
+    Aborted,
 }
 
 fn write_result<W>(w: &mut W, s: String)
@@ -462,13 +727,13 @@ where
     W: Write,
 {
     // Special annoying case since the blank line will be parsed as a comment.
-    if s.is_empty() || s == "\n" {
+    if s.is_empty() {
         w.write_str("----\n").unwrap();
     } else if !s.ends_with('\n') {
         w.write_str("----\n----\n").unwrap();
         w.write_str(&s).unwrap();
         w.write_str("\n----\n---- (no newline)\n").unwrap();
-    } else if s.contains("\n\n") {
+    } else if s.contains("\n\n") || s == "\n" {
         w.write_str("----\n----\n").unwrap();
         w.write_str(&s).unwrap();
         w.write_str("----\n----\n").unwrap();
@@ -478,29 +743,143 @@ where
     }
 }
 
+// Describes a test case that took too long.
+#[cfg(feature = "async")]
+fn timed_out(filename: &str, case: &TestCase, timeout: Duration) -> String {
+    format!(
+        "failure:\n{}:{}:\n{}\ntimed out after {:?}\n",
+        filename, case.line_number, case.input, timeout
+    )
+}
+
+// Describes the failure a test case's outcome amounts to, if it is one.
+fn check(filename: &str, case: &TestCase, outcome: &Outcome) -> Option<String> {
+    match outcome {
+        Outcome::Passed | Outcome::Skipped | Outcome::Rewritten(_) => None,
+        Outcome::Failed(output) => Some(mismatch(filename, case, output)),
+        Outcome::Errored(err) => Some(errored(filename, case, err)),
+    }
+}
+
+// Describes a test case that returned an error.
+fn errored(filename: &str, case: &TestCase, err: &str) -> String {
+    format!(
+        "failure:\n{}:{}:\n{}\n{}",
+        filename, case.line_number, case.input, err
+    )
+}
+
+// Describes a test case that panicked.
+fn panicked(filename: &str, case: &TestCase, payload: &(dyn Any + Send)) -> String {
+    format!(
+        "failure:\n{}:{}:\n{}\npanicked: {}\n",
+        filename,
+        case.line_number,
+        case.input,
+        panic_message(payload)
+    )
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<unknown panic payload>".to_string()
+    }
+}
+
+// Writes `contents` to `path` by way of a temporary file in the same directory, so that an
+// interrupted write can never leave a truncated file behind.
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    // If `path` is a symlink, the file it points to is the one to replace, not the link.
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    // Renaming over a read-only file would work, but writing to one shouldn't.
+    let permissions = match fs::metadata(path) {
+        Ok(meta) if meta.permissions().readonly() => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "file is read-only",
+            ))
+        }
+        Ok(meta) => Some(meta.permissions()),
+        Err(_) => None,
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    // Starting with a dot means walks will ignore it if it gets left behind.
+    let tmp = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        // The rename isn't durable until the directory is synced too. Not every platform can open
+        // a directory to do that, so this is best-effort.
+        if let Ok(dir) = fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// Describes a test case whose output didn't match what was expected.
+fn mismatch(filename: &str, case: &TestCase, actual: &str) -> String {
+    let first_line = case.layout.expected_line().unwrap_or(case.line_number);
+    format!(
+        "failure:\n{}:{}:\n{}\n{}",
+        filename,
+        case.line_number,
+        case.input,
+        diff::unified_diff(&case.expected, actual, first_line, diff::use_color())
+    )
+}
+
 impl TestFile {
-    fn new(filename: &PathBuf) -> Result<Self, DataDrivenError> {
-        let contents = fs::read_to_string(filename).map_err(DataDrivenError::Io)?;
-        let stanzas =
-            Self::parse(&contents).map_err(|e| e.with_filename(filename.display().to_string()))?;
+    fn new(filename: &Path) -> Result<Self, DataDrivenError> {
+        let with_filename = |e: DataDrivenError| e.with_filename(filename.display().to_string());
+        let contents = fs::read_to_string(filename)
+            .map_err(DataDrivenError::Io)
+            .map_err(with_filename)?;
+        let stanzas = Self::parse(&contents).map_err(with_filename)?;
         Ok(TestFile {
             stanzas,
             filename: filename.to_string_lossy().to_string(),
-            failure: None,
+            failures: Default::default(),
+            results: Vec::new(),
+            rewrote: false,
+            config: Default::default(),
+            contents,
         })
     }
 
-    /// Run each test in this file in sequence by calling `f` on it. If any test fails, execution
-    /// halts. If the REWRITE environment variable is set, it will rewrite each file as it
-    /// processes it.
+    /// Run each test in this file in sequence by calling `f` on it. Failures are collected and
+    /// reported by the walk, and if too many tests fail in a row the rest of the file is skipped
+    /// (see `Walk::max_consecutive_failures`). If the REWRITE environment variable is set, it will
+    /// rewrite each file as it processes it (see `RewriteMode`). The file itself is left as it
+    /// was parsed, so it can be run again, and how each test case did is kept in `results`.
     pub fn run<F, R>(&mut self, f: F)
     where
         F: FnMut(&mut TestCase) -> R,
         R: TestCaseResult,
     {
-        match env::var("REWRITE") {
-            Ok(_) => self.run_rewrite(f),
-            Err(_) => self.run_normal(f),
+        match self.config.rewrite {
+            Some(mode) => self.run_rewrite(f, mode),
+            None => self.run_normal(f),
         }
     }
 
@@ -509,44 +888,239 @@ impl TestFile {
         F: FnMut(&mut TestCase) -> R,
         R: TestCaseResult,
     {
-        for stanza in &mut self.stanzas {
-            if let Stanza::Test(case) = stanza {
-                let result = f(case);
-                match result.result() {
-                    Ok(result) => {
-                        if result != case.expected {
-                            self.failure = Some(format!(
-                                "failure:\n{}:{}:\n{}\nexpected:\n{}\nactual:\n{}",
-                                self.filename, case.line_number, case.input, case.expected, result
-                            ));
-                            // Yeah, ok, we're done here.
-                            break;
-                        }
-                    }
-                    Err(err) => {
-                        self.failure = Some(format!(
-                            "failure:\n{}:{}:\n{}\n{}",
-                            self.filename, case.line_number, case.input, err
-                        ));
-                    }
-                }
+        let max_consecutive = self.config.max_consecutive_failures;
+        let selections = self.start();
+        // Handlers get their own copy of each test case, so that taking its arguments leaves the
+        // file as it was parsed, ready to be run again.
+        let cases: Vec<_> = self.cases().cloned().collect();
+        for (i, (mut case, selection)) in cases.into_iter().zip(selections).enumerate() {
+            if selection == Selection::Skip {
+                break;
             }
+            let result = match self.run_case(i, selection, &mut case, &mut f) {
+                Ran::Returned(result) => self.config.output(result),
+                Ran::RunOnly => continue,
+                Ran::Aborted => break,
+            };
+            if self.compare(i, &case, result, max_consecutive) {
+                break;
+            }
+        }
+    }
+
+    fn run_rewrite<F, R>(&mut self, f: F, mode: RewriteMode)
+    where
+        F: FnMut(&mut TestCase) -> R,
+        R: TestCaseResult,
+    {
+        match self.rewrite_outputs(f, mode) {
+            Some(outputs) => self.write_rewrite(outputs, mode),
+            None => self.not_rewritten(),
         }
     }
 
-    fn run_rewrite<F, R>(&mut self, mut f: F)
+    // Runs every selected test case for a rewrite, returning the output to write for each test
+    // case, or None if the file should be left alone.
+    fn rewrite_outputs<F, R>(&mut self, mut f: F, mode: RewriteMode) -> Option<Vec<String>>
     where
         F: FnMut(&mut TestCase) -> R,
         R: TestCaseResult,
     {
+        let mut outputs = Vec::new();
+        let selections = self.start();
+        let cases: Vec<_> = self.cases().cloned().collect();
+        for (i, (mut case, selection)) in cases.into_iter().zip(selections).enumerate() {
+            // Test cases that weren't selected keep the output they already had.
+            if selection == Selection::Skip {
+                outputs.push(case.expected);
+                continue;
+            }
+            let result = match self.run_case(i, selection, &mut case, &mut f) {
+                Ran::Returned(result) => result,
+                Ran::RunOnly => {
+                    outputs.push(case.expected);
+                    continue;
+                }
+                Ran::Aborted => return None,
+            };
+            outputs.push(self.rewrite_output(i, &case, result, mode)?);
+        }
+        Some(outputs)
+    }
+
+    // Runs a single test case with `f`, under its timeout, reporting that it started and timing
+    // it.
+    fn run_case<F, R>(
+        &mut self,
+        i: usize,
+        selection: Selection,
+        case: &mut TestCase,
+        f: &mut F,
+    ) -> Ran<R>
+    where
+        F: FnMut(&mut TestCase) -> R,
+    {
+        let filename = &self.filename;
+        let _watchdog = self
+            .config
+            .timeout_for(case)
+            .map(|timeout| timeout::watchdog(timeout, filename, case));
+        self.config.reporters.on_case_start(&self.filename, case);
+        let started = Instant::now();
+        let result = catch_unwind(AssertUnwindSafe(|| f(case)));
+        self.results[i].duration = started.elapsed();
+        match result {
+            Ok(result) => self.returned(i, selection, result),
+            Err(payload) => {
+                let failure = panicked(&self.filename, case, payload.as_ref());
+                self.abort_case(i, failure, Outcome::panicked(payload.as_ref()))
+            }
+        }
+    }
+
+    // What to do with what a handler returned for the `i`th test case. Test cases that only ran
+    // to build up state for the ones a filter selected are done with.
+    fn returned<R>(&mut self, i: usize, selection: Selection, result: R) -> Ran<R> {
+        if selection == Selection::RunOnly {
+            self.config
+                .finish_case(&self.filename, &mut self.results[i], Outcome::Skipped);
+            return Ran::RunOnly;
+        }
+        Ran::Returned(result)
+    }
+
+    // Records the `i`th test case's handler panicking or timing out. Whatever state the test cases
+    // share is suspect now, so the rest of the file is skipped.
+    fn abort_case<R>(&mut self, i: usize, failure: String, outcome: Outcome) -> Ran<R> {
+        self.failures.messages.push(failure);
+        self.config
+            .finish_case(&self.filename, &mut self.results[i], outcome);
+        Ran::Aborted
+    }
+
+    // Compares the `i`th test case's output to what it expected, and records how it did. Returns
+    // true if enough test cases have failed in a row that the rest of the file should be skipped.
+    fn compare(
+        &mut self,
+        i: usize,
+        case: &TestCase,
+        result: Result<String, String>,
+        max_consecutive: Option<usize>,
+    ) -> bool {
+        let outcome = Outcome::of(case, result);
+        let failure = check(&self.filename, case, &outcome);
+        self.config
+            .finish_case(&self.filename, &mut self.results[i], outcome);
+        match failure {
+            None => {
+                self.failures.pass();
+                false
+            }
+            Some(failure) => self.failures.fail(failure, max_consecutive),
+        }
+    }
+
+    // Records how the `i`th test case did in a rewrite, returning the output to write for it, or
+    // None if the file should be left alone.
+    fn rewrite_output<R: TestCaseResult>(
+        &mut self,
+        i: usize,
+        case: &TestCase,
+        result: R,
+        mode: RewriteMode,
+    ) -> Option<String> {
+        let result = self.config.output(result);
+        self.config.finish_case(
+            &self.filename,
+            &mut self.results[i],
+            Outcome::of(case, result.clone()).rewritten_by(mode),
+        );
+        match result {
+            Ok(output) => Some(output),
+            Err(err) => {
+                // There's nothing sensible to write for this test case, so leave the file alone.
+                let failure = errored(&self.filename, case, &err);
+                self.failures.messages.push(failure);
+                None
+            }
+        }
+    }
+
+    // Clears out the results of any earlier run, and works out which test cases this run selects.
+    // Failures of earlier runs stay, so that a file run against several implementations fails if
+    // any of them do, but a new run gets a fresh count of failures in a row.
+    fn start(&mut self) -> Vec<Selection> {
+        self.results = self.cases().map(CaseResult::skipped).collect();
+        self.rewrote = false;
+        self.failures.consecutive = 0;
+        self.config.filter.select(self.cases())
+    }
+
+    /// The result of each test case from the last time this file was run, in order. Test cases
+    /// that weren't run, or that only ran to build up state for the test cases selected by a
+    /// filter, are `Outcome::Skipped`.
+    pub fn results(&self) -> &[CaseResult] {
+        &self.results
+    }
+
+    // Writes the file back out with `outputs` as the expected output of each test case, in order.
+    fn write_rewrite(&mut self, outputs: Vec<String>, mode: RewriteMode) {
+        let s = match mode {
+            RewriteMode::All => self.rewritten(outputs),
+            RewriteMode::Failing => match self.spliced(outputs) {
+                Some(s) => s,
+                None => return,
+            },
+            RewriteMode::Check => {
+                let rewritten = self.rewritten(outputs);
+                if rewritten != self.contents {
+                    let diff = diff::unified_diff(&self.contents, &rewritten, 1, diff::use_color());
+                    self.failures.messages.push(format!(
+                        "failure:\n{}: rewriting would change the file:\n{}",
+                        self.filename, diff
+                    ));
+                }
+                return;
+            }
+        };
+        if s == self.contents {
+            return;
+        }
+        match write_atomic(Path::new(&self.filename), &s) {
+            Ok(()) => {
+                self.rewrote = true;
+                self.config.reporters.on_rewrite(&self.filename, &s);
+            }
+            Err(err) => {
+                let err = DataDrivenError::Io(err).with_filename(self.filename.clone());
+                self.failures
+                    .messages
+                    .push(format!("failure:\nrewriting {}", err));
+                self.not_rewritten();
+            }
+        }
+    }
+
+    // The file was left alone after all, so the test cases that would have been rewritten failed.
+    fn not_rewritten(&mut self) {
+        for result in &mut self.results {
+            if let Outcome::Rewritten(output) = &result.outcome {
+                result.outcome = Outcome::Failed(output.clone());
+            }
+        }
+    }
+
+    // Renders the whole file from scratch with new expected outputs.
+    fn rewritten(&self, outputs: Vec<String>) -> String {
+        let mut outputs = outputs.into_iter();
         let mut s = String::new();
-        for stanza in &mut self.stanzas {
+        for stanza in &self.stanzas {
             match stanza {
                 Stanza::Test(case) => {
                     s.push_str(&case.directive_line);
                     s.push('\n');
                     s.push_str(&case.input);
-                    write_result(&mut s, f(case).result().unwrap());
+                    write_result(&mut s, outputs.next().unwrap());
                 }
                 Stanza::Comment(c) => {
                     s.push_str(c.as_str());
@@ -554,13 +1128,53 @@ impl TestFile {
                 }
             }
         }
-        // TODO(justin): surface these errors somehow?
-        fs::write(&self.filename, s).unwrap();
+        s
+    }
+
+    // Replaces just the expected blocks of test cases whose output changed, leaving every other
+    // byte of the file alone. Returns None if nothing changed.
+    fn spliced(&self, outputs: Vec<String>) -> Option<String> {
+        let mut s = String::new();
+        let mut copied = 0;
+        let mut changed = false;
+        for (case, output) in self.cases().zip(outputs) {
+            if output == case.expected {
+                continue;
+            }
+            changed = true;
+            let range = case.layout.expected_range.clone();
+            s.push_str(&self.contents[copied..range.start]);
+            // A test case with no separator runs to the end of the file, which might not end in a
+            // newline.
+            if !s.is_empty() && !s.ends_with('\n') {
+                s.push('\n');
+            }
+            write_result(&mut s, output);
+            copied = range.end;
+        }
+        if !changed {
+            return None;
+        }
+        s.push_str(&self.contents[copied..]);
+        Some(s)
+    }
+
+    fn cases(&self) -> impl Iterator<Item = &TestCase> {
+        self.stanzas.iter().filter_map(|stanza| match stanza {
+            Stanza::Test(case) => Some(case),
+            Stanza::Comment(_) => None,
+        })
     }
 
     fn parse(f: &str) -> Result<Vec<Stanza>, DataDrivenError> {
         let mut stanzas = vec![];
         let lines: Vec<&str> = f.lines().collect();
+        // The byte offset each line starts at, plus the end of the file.
+        let mut line_starts = vec![0];
+        line_starts.extend(f.split_inclusive('\n').scan(0, |end, line| {
+            *end += line.len();
+            Some(*end)
+        }));
         let mut i = 0;
         while i < lines.len() {
             // TODO(justin): hacky implementation of comments
@@ -580,9 +1194,18 @@ impl TestFile {
 
             let mut parser = DirectiveParser::new(&line);
             let directive_line = lines[i].to_string();
-            let (directive, args) = parser
+            let (directive, mut args) = parser
                 .parse_directive()
                 .map_err(|e| e.with_line(line_number))?;
+            // A `timeout` argument that isn't a single duration is left for the handler, since
+            // test files could already be using one of their own.
+            let timeout = match args.get("timeout").map(Vec::as_slice) {
+                Some([timeout]) => timeout::parse_duration(timeout).ok(),
+                _ => None,
+            };
+            if timeout.is_some() {
+                args.remove("timeout");
+            }
 
             i += 1;
             let mut input = String::new();
@@ -592,12 +1215,18 @@ impl TestFile {
                 input.push('\n');
                 i += 1;
             }
+            let mut layout = Layout {
+                separator_line: if i < lines.len() { Some(i + 1) } else { None },
+                expected_range: line_starts[i]..f.len(),
+                ..Default::default()
+            };
             i += 1;
             // If there is a second ----, we are in blank-line mode.
             let blank_mode = i < lines.len() && lines[i] == "----";
             if blank_mode {
                 i += 1;
             }
+            layout.double_separator = blank_mode;
 
             // Then slurp up the expected.
             let mut expected = String::new();
@@ -629,6 +1258,13 @@ impl TestFile {
                 i += 1;
             }
 
+            layout.expected_range.end = line_starts[i.min(lines.len())];
+
+            // The line after a double-separator block is skipped even if it isn't blank.
+            if blank_mode && i < lines.len() && lines[i].trim() != "" {
+                layout.stray_line = Some(i + 1);
+            }
+
             stanzas.push(Stanza::Test(TestCase {
                 directive_line,
                 directive: directive.to_string(),
@@ -636,6 +1272,8 @@ impl TestFile {
                 args,
                 expected,
                 line_number,
+                layout,
+                timeout,
             }));
             i += 1;
             if i < lines.len() {
@@ -647,18 +1285,22 @@ impl TestFile {
     }
 }
 
-fn file_list(dir: &str) -> Vec<PathBuf> {
-    let mut file_prefix = PathBuf::from(dir);
-    if let Ok(p) = env::var("RUN") {
-        file_prefix = file_prefix.join(p);
+fn file_list(dir: &str) -> Result<Vec<PathBuf>, String> {
+    let file_prefix = PathBuf::from(dir);
+    // `RUN` picks out files under a directory, so a walk of a single file, like each of the tests
+    // `#[datadriven::test]` generates, always runs it.
+    if let Ok(run) = env::var("RUN") {
+        if file_prefix.is_dir() {
+            return filter::run_files(&file_prefix, &run);
+        }
     }
 
     if file_prefix.is_dir() {
-        test_files(PathBuf::from(dir)).unwrap()
+        test_files(file_prefix).map_err(|err| format!("listing test files in {}: {}", dir, err))
     } else if file_prefix.exists() {
-        vec![file_prefix]
+        Ok(vec![file_prefix])
     } else {
-        vec![]
+        Ok(vec![])
     }
 }
 
@@ -669,39 +1311,29 @@ where
     F: FnMut(TestFile) -> T,
     T: Future<Output = TestFile>,
 {
-    walk_async_exclusive(dir, f, |_| false).await;
+    Walk::new(dir).run_async(f).await;
+}
+
+/// The async equivalent of `try_walk`. Must return the passed `TestFile`.
+#[cfg(feature = "async")]
+pub async fn try_walk_async<F, T>(dir: &str, f: F) -> Report
+where
+    F: FnMut(TestFile) -> T,
+    T: Future<Output = TestFile>,
+{
+    Walk::new(dir).try_run_async(f).await
 }
 
 /// The same as `walk_async` but accepts an additional matcher to exclude matching files from being
 /// tested.
 #[cfg(feature = "async")]
-pub async fn walk_async_exclusive<F, T, M>(dir: &str, mut f: F, exclusion_matcher: M)
+pub async fn walk_async_exclusive<F, T, M>(dir: &str, f: F, exclusion_matcher: M)
 where
     F: FnMut(TestFile) -> T,
     T: Future<Output = TestFile>,
     M: Fn(&TestFile) -> bool,
 {
-    // Accumulate failures until the end since Rust doesn't let us "fail but keep going" in a test.
-    let mut failures = Vec::new();
-    for file in file_list(dir) {
-        let tf = TestFile::new(&file).unwrap();
-        if exclusion_matcher(&tf) {
-            continue;
-        }
-        let tf = f(tf).await;
-        if let Some(fail) = tf.failure {
-            failures.push(fail);
-        }
-    }
-
-    if !failures.is_empty() {
-        let mut msg = String::new();
-        for f in failures {
-            msg.push_str(&f);
-            msg.push('\n');
-        }
-        panic!("{}", msg);
-    }
+    Walk::new(dir).exclude(exclusion_matcher).run_async(f).await;
 }
 
 /// The same as `walk_async` but can run `concurrent` files in parallel.
@@ -711,7 +1343,21 @@ where
     F: FnMut(TestFile) -> T,
     T: Future<Output = TestFile>,
 {
-    walk_async_concurrent_exclusive(dir, concurrency, f, |_| false).await;
+    Walk::new(dir).run_async_concurrent(concurrency, f).await;
+}
+
+/// The same as `walk_async_concurrent` but runs each file as its own task, handed to `spawn` (like
+/// `tokio::spawn`), so that files run in parallel on a multi-threaded runtime.
+#[cfg(feature = "async")]
+pub async fn walk_async_spawned<F, T, S, J>(dir: &str, concurrency: usize, spawn: S, f: F)
+where
+    F: FnMut(TestFile) -> T,
+    T: Future<Output = TestFile> + Send + 'static,
+    S: Fn(BoxFuture<'static, ()>) -> J,
+{
+    Walk::new(dir)
+        .run_async_spawned(concurrency, spawn, f)
+        .await;
 }
 
 /// The same as `walk_async_exclusive` but can run `concurrent` files in parallel.
@@ -719,111 +1365,243 @@ where
 pub async fn walk_async_concurrent_exclusive<F, T, M>(
     dir: &str,
     concurrency: usize,
-    mut f: F,
+    f: F,
     exclusion_matcher: M,
 ) where
     F: FnMut(TestFile) -> T,
     T: Future<Output = TestFile>,
     M: Fn(&TestFile) -> bool,
 {
-    use futures::StreamExt;
+    Walk::new(dir)
+        .exclude(exclusion_matcher)
+        .run_async_concurrent(concurrency, f)
+        .await;
+}
 
-    // Create futures list so that we can execute them in parallel
-    let mut futures = futures::stream::iter(file_list(dir).into_iter().filter_map(|file| {
-        let tf = TestFile::new(&file).unwrap();
-        if exclusion_matcher(&tf) {
-            return None;
-        }
-        Some(f(tf))
-    }))
-    .buffered(concurrency);
+#[cfg(feature = "async")]
+impl TestFile {
+    /// The async equivalent of `run`. `f` gets its own copy of each test case, so it can hold on
+    /// to it across awaits. Like with `run`, the future can produce a `Result`, and errors are
+    /// reported as failures.
+    pub async fn run_async<F, T, R>(&mut self, f: F)
+    where
+        F: FnMut(TestCase) -> T,
+        T: Future<Output = R>,
+        R: TestCaseResult,
+    {
+        self.run_handler_async(Owned(f)).await;
+    }
 
-    // Accumulate failures until the end since Rust doesn't let us "fail but keep going" in a test.
-    let mut failures = Vec::new();
+    /// The same as `run_async`, but `f` borrows `state` and the test case for as long as its
+    /// future runs, so state can be shared between test cases without cloning or locking it, and
+    /// the test case's arguments can be taken and checked like they are with `run`.
+    ///
+    /// ```no_run
+    /// # async fn example() {
+    /// use datadriven::walk_async;
+    /// use futures::future::FutureExt;
+    ///
+    /// walk_async("tests/testdata", |mut f| async move {
+    ///     let mut total = 0;
+    ///     f.run_async_with(&mut total, |total, case| {
+    ///         async move {
+    ///             let n: i64 = case.take_arg("n")?;
+    ///             case.expect_empty()?;
+    ///             *total += n;
+    ///             Ok::<_, datadriven::DataDrivenError>(format!("{}\n", total))
+    ///         }
+    ///         .boxed()
+    ///     })
+    ///     .await;
+    ///     f
+    /// })
+    /// .await;
+    /// # }
+    /// ```
+    pub async fn run_async_with<S, F, R>(&mut self, state: &mut S, f: F)
+    where
+        F: for<'a> FnMut(&'a mut S, &'a mut TestCase) -> BoxFuture<'a, R>,
+        R: TestCaseResult,
+    {
+        self.run_handler_async(WithState { state, f }).await;
+    }
 
-    while let Some(tf) = futures.next().await {
-        if let Some(fail) = tf.failure {
-            failures.push(fail);
+    async fn run_handler_async<H, R>(&mut self, handler: H)
+    where
+        H: AsyncHandler<R>,
+        R: TestCaseResult,
+    {
+        match self.config.rewrite {
+            Some(mode) => self.run_rewrite_async(handler, mode).await,
+            None => self.run_normal_async(handler).await,
         }
     }
 
-    if !failures.is_empty() {
-        let mut msg = String::new();
-        for f in failures {
-            msg.push_str(&f);
-            msg.push('\n');
+    async fn run_normal_async<H, R>(&mut self, mut handler: H)
+    where
+        H: AsyncHandler<R>,
+        R: TestCaseResult,
+    {
+        let max_consecutive = self.config.max_consecutive_failures;
+        let selections = self.start();
+        // Handlers get their own copy of each test case, so that taking its arguments leaves the
+        // file as it was parsed, ready to be run again.
+        let cases: Vec<_> = self.cases().cloned().collect();
+        for (i, (mut case, selection)) in cases.into_iter().zip(selections).enumerate() {
+            if selection == Selection::Skip {
+                break;
+            }
+            let result = match self
+                .run_case_async(i, selection, &mut case, &mut handler)
+                .await
+            {
+                Ran::Returned(result) => self.config.output(result),
+                Ran::RunOnly => continue,
+                Ran::Aborted => break,
+            };
+            if self.compare(i, &case, result, max_consecutive) {
+                break;
+            }
         }
-        panic!("{}", msg);
     }
-}
 
-#[cfg(feature = "async")]
-impl TestFile {
-    /// The async equivalent of `run`.
-    pub async fn run_async<F, T>(&mut self, f: F)
+    async fn run_rewrite_async<H, R>(&mut self, handler: H, mode: RewriteMode)
     where
-        F: FnMut(TestCase) -> T,
-        T: Future<Output = String>,
+        H: AsyncHandler<R>,
+        R: TestCaseResult,
     {
-        match env::var("REWRITE") {
-            Ok(_) => self.run_rewrite_async(f).await,
-            Err(_) => self.run_normal_async(f).await,
+        match self.rewrite_outputs_async(handler, mode).await {
+            Some(outputs) => self.write_rewrite(outputs, mode),
+            None => self.not_rewritten(),
         }
     }
 
-    async fn run_normal_async<F, T>(&mut self, mut f: F)
+    // The async equivalent of `rewrite_outputs`.
+    async fn rewrite_outputs_async<H, R>(
+        &mut self,
+        mut handler: H,
+        mode: RewriteMode,
+    ) -> Option<Vec<String>>
     where
-        F: FnMut(TestCase) -> T,
-        T: Future<Output = String>,
+        H: AsyncHandler<R>,
+        R: TestCaseResult,
     {
-        for stanza in self.stanzas.drain(..) {
-            if let Stanza::Test(case) = stanza {
-                let original_case = case.clone();
-                let result = f(case).await;
-                if result != original_case.expected {
-                    self.failure = Some(format!(
-                        "failure:\n{}:{}:\n{}\nexpected:\n{}\nactual:\n{}",
-                        self.filename,
-                        original_case.line_number,
-                        original_case.input,
-                        original_case.expected,
-                        result
-                    ));
-                    // Yeah, ok, we're done here.
-                    break;
-                }
+        let mut outputs = Vec::new();
+        let selections = self.start();
+        let cases: Vec<_> = self.cases().cloned().collect();
+        for (i, (mut case, selection)) in cases.into_iter().zip(selections).enumerate() {
+            // Test cases that weren't selected keep the output they already had.
+            if selection == Selection::Skip {
+                outputs.push(case.expected);
+                continue;
             }
+            let result = match self
+                .run_case_async(i, selection, &mut case, &mut handler)
+                .await
+            {
+                Ran::Returned(result) => result,
+                Ran::RunOnly => {
+                    outputs.push(case.expected);
+                    continue;
+                }
+                Ran::Aborted => return None,
+            };
+            outputs.push(self.rewrite_output(i, &case, result, mode)?);
         }
+        Some(outputs)
     }
 
-    async fn run_rewrite_async<F, T>(&mut self, mut f: F)
+    // The async equivalent of `run_case`.
+    async fn run_case_async<H, R>(
+        &mut self,
+        i: usize,
+        selection: Selection,
+        case: &mut TestCase,
+        handler: &mut H,
+    ) -> Ran<R>
     where
-        F: FnMut(TestCase) -> T,
-        T: Future<Output = String>,
+        H: AsyncHandler<R>,
     {
-        let mut s = String::new();
-        for stanza in self.stanzas.drain(..) {
-            match stanza {
-                Stanza::Test(case) => {
-                    s.push_str(&case.directive_line);
-                    s.push('\n');
-                    s.push_str(&case.input);
-                    write_result(&mut s, f(case).await);
-                }
-                Stanza::Comment(c) => {
-                    s.push_str(&c);
-                    s.push('\n');
-                }
+        let timeout = self.config.timeout_for(case);
+        self.config.reporters.on_case_start(&self.filename, case);
+        let started = Instant::now();
+        let result = AssertUnwindSafe(handler.call(case)).catch_unwind();
+        let result = timeout::with_timeout(result, timeout).await;
+        self.results[i].duration = started.elapsed();
+        match result {
+            Some(Ok(result)) => self.returned(i, selection, result),
+            Some(Err(payload)) => {
+                let failure = panicked(&self.filename, case, payload.as_ref());
+                self.abort_case(i, failure, Outcome::panicked(payload.as_ref()))
+            }
+            None => {
+                let timeout = timeout.unwrap();
+                let failure = timed_out(&self.filename, case, timeout);
+                self.abort_case(i, failure, Outcome::timed_out(timeout))
             }
         }
-        // TODO(justin): surface these errors somehow?
-        fs::write(&self.filename, s).unwrap();
+    }
+}
+
+// Something that runs test cases asynchronously, so that `run_async` and `run_async_with` can
+// share the work of running a file.
+#[cfg(feature = "async")]
+trait AsyncHandler<R> {
+    type Output<'a>: Future<Output = R>
+    where
+        Self: 'a;
+
+    fn call<'a>(&'a mut self, case: &'a mut TestCase) -> Self::Output<'a>;
+}
+
+// A handler that gets its own copy of each test case.
+#[cfg(feature = "async")]
+struct Owned<F>(F);
+
+#[cfg(feature = "async")]
+impl<F, T, R> AsyncHandler<R> for Owned<F>
+where
+    F: FnMut(TestCase) -> T,
+    T: Future<Output = R>,
+{
+    type Output<'a>
+        = T
+    where
+        Self: 'a;
+
+    fn call<'a>(&'a mut self, case: &'a mut TestCase) -> T {
+        (self.0)(case.clone())
+    }
+}
+
+// A handler that borrows some state and the test case.
+#[cfg(feature = "async")]
+struct WithState<'s, S, F> {
+    state: &'s mut S,
+    f: F,
+}
+
+#[cfg(feature = "async")]
+impl<S, F, R> AsyncHandler<R> for WithState<'_, S, F>
+where
+    F: for<'a> FnMut(&'a mut S, &'a mut TestCase) -> BoxFuture<'a, R>,
+{
+    type Output<'a>
+        = BoxFuture<'a, R>
+    where
+        Self: 'a;
+
+    fn call<'a>(&'a mut self, case: &'a mut TestCase) -> BoxFuture<'a, R> {
+        (self.f)(self.state, case)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    // Not a glob import, which would make `#[test]` ambiguous with `datadriven::test`.
+    #[cfg(feature = "async")]
+    use super::walk_async_concurrent;
+    use super::{diff, natural_cmp, walk, DataDrivenError, DirectiveParser};
 
     // That's dogfooding baby!
     #[test]
@@ -842,6 +1620,46 @@ mod tests {
         });
     }
 
+    #[test]
+    fn unified_diff() {
+        walk("tests/diff", |f| {
+            f.run(|s| {
+                let first_line = s.try_take_arg("first-line")?.unwrap_or(1);
+                s.expect_empty()?;
+                // Inputs can't spell out a missing trailing newline, so `~~~` at the end of a line
+                // stands in for one.
+                let (expected, actual) = s.input.split_once("~~~\n").unwrap();
+                let actual = actual.strip_suffix("~~~\n").unwrap_or(actual);
+                Ok::<_, DataDrivenError>(diff::unified_diff(expected, actual, first_line, false))
+            });
+        });
+    }
+
+    #[test]
+    fn unified_diff_large() {
+        // Too many differing lines to find the smallest diff, so every expected line is replaced by
+        // every actual one, even those that are the same.
+        let expected: String = (0..3000).map(|i| format!("{}\n", i)).collect();
+        let actual: String = (0..3000).map(|i| format!("{}\n", i * 2 + 1)).collect();
+        let diff = diff::unified_diff(&expected, &actual, 1, false);
+        let lines: Vec<&str> = diff.lines().collect();
+        assert_eq!(lines[2], "@@ -1,3000 +1,3000 @@");
+        assert_eq!(lines.len(), 3 + 6000);
+        assert!(lines[3..3003].iter().all(|l| l.starts_with('-')));
+        assert!(lines[3003..].iter().all(|l| l.starts_with('+')));
+    }
+
+    #[test]
+    fn natural_order() {
+        walk("tests/natural_order", |f| {
+            f.run(|s| {
+                let mut names: Vec<_> = s.input.lines().collect();
+                names.sort_by(|a, b| natural_cmp(a, b));
+                names.iter().map(|n| format!("{}\n", n)).collect::<String>()
+            });
+        });
+    }
+
     // That's async dogfooding baby!
     #[cfg(feature = "async")]
     #[tokio::test]
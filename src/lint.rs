@@ -0,0 +1,154 @@
+//! Checks for common mistakes in test files.
+//!
+//! These are all things that parse fine, but almost certainly don't mean what their author
+//! intended, like a test case that was swallowed into the expected output of the one before it
+//! because of a missing blank line.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{test_files, DataDrivenError, Stanza, TestCase, TestFile};
+
+/// The kind of mistake a lint warning is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// The input of a test case was never closed by a `----`.
+    MissingSeparator,
+    /// The expected output contains a `----` line followed by more output, which usually means a
+    /// blank line is missing and the next test case was read as expected output.
+    SwallowedCase,
+    /// The expected output ends in a `----` line.
+    TrailingSeparator,
+    /// Text directly after a double-separator block, which is ignored.
+    StrayText,
+    /// A test case with the same directive and input as an earlier one but different expected
+    /// output.
+    DuplicateCase,
+}
+
+/// A problem found in a test file.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    /// The file the problem is in.
+    pub filename: String,
+    /// The line the problem is on.
+    pub line: usize,
+    /// What sort of problem it is.
+    pub kind: LintKind,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.filename, self.line, self.message)
+    }
+}
+
+/// Lints the contents of a test file. `filename` is only used for reporting.
+pub fn lint(filename: &str, contents: &str) -> Result<Vec<Warning>, DataDrivenError> {
+    let stanzas = TestFile::parse(contents).map_err(|e| e.with_filename(filename.to_string()))?;
+    Ok(lint_stanzas(filename, &stanzas))
+}
+
+/// Lints every test file under `path` (or just `path`, if it is a file).
+pub fn lint_path(path: &Path) -> Result<Vec<Warning>, DataDrivenError> {
+    let files = if path.is_dir() {
+        test_files(path.to_path_buf())?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let mut warnings = Vec::new();
+    for file in files {
+        let filename = file.display().to_string();
+        let contents = fs::read_to_string(&file)
+            .map_err(|e| DataDrivenError::Io(e).with_filename(filename.clone()))?;
+        warnings.extend(lint(&filename, &contents)?);
+    }
+    Ok(warnings)
+}
+
+pub(crate) fn lint_stanzas(filename: &str, stanzas: &[Stanza]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut warn = |line: usize, kind: LintKind, message: String| {
+        warnings.push(Warning {
+            filename: filename.to_string(),
+            line,
+            kind,
+            message,
+        })
+    };
+
+    let mut seen: Vec<&TestCase> = Vec::new();
+    for stanza in stanzas {
+        let case = match stanza {
+            Stanza::Test(case) => case,
+            Stanza::Comment(_) => continue,
+        };
+        let layout = &case.layout;
+
+        let expected_line = match layout.expected_line() {
+            Some(line) => line,
+            None => {
+                warn(
+                    case.line_number,
+                    LintKind::MissingSeparator,
+                    "test case has no `----` separator, so its input runs to the end of the file"
+                        .into(),
+                );
+                continue;
+            }
+        };
+
+        if !layout.double_separator {
+            let lines: Vec<&str> = case.expected.lines().collect();
+            if let Some(idx) = lines.iter().position(|l| *l == "----") {
+                if idx + 1 < lines.len() {
+                    warn(
+                        expected_line + idx,
+                        LintKind::SwallowedCase,
+                        "expected output contains a `----` separator; is a blank line missing \
+                         before the next test case?"
+                            .into(),
+                    );
+                } else {
+                    warn(
+                        expected_line + idx,
+                        LintKind::TrailingSeparator,
+                        "expected output ends with a `----` that has no output after it".into(),
+                    );
+                }
+            }
+        }
+
+        if let Some(line) = layout.stray_line {
+            warn(
+                line,
+                LintKind::StrayText,
+                "text directly after a double-separator block is ignored; add a blank line \
+                 before it"
+                    .into(),
+            );
+        }
+
+        let duplicate = seen.iter().find(|prev| {
+            prev.directive == case.directive && prev.args == case.args && prev.input == case.input
+        });
+        if let Some(prev) = duplicate {
+            if prev.expected != case.expected {
+                warn(
+                    case.line_number,
+                    LintKind::DuplicateCase,
+                    format!(
+                        "same directive and input as the test case on line {}, but different \
+                         expected output",
+                        prev.line_number
+                    ),
+                );
+            }
+        }
+        seen.push(case);
+    }
+    warnings
+}
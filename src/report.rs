@@ -0,0 +1,239 @@
+//! Structured results of walks, for building harnesses and tooling on top of them rather than
+//! parsing panic messages.
+
+use std::any::Any;
+use std::fmt;
+use std::time::Duration;
+
+use crate::{panic_message, RewriteMode, TestCase, TestFile};
+
+/// Everything that happened in a walk. Returned by `try_walk` and the `Walk::try_run` family.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Each file that was run, in the order they were walked. Excluded files aren't included.
+    pub files: Vec<FileReport>,
+    /// Failures of the walk as a whole rather than any one file, like there being nothing to run
+    /// (see `Walk::allow_empty`).
+    pub errors: Vec<String>,
+    /// How long the walk took.
+    pub duration: Duration,
+    /// The seed the files were shuffled with, if they were (see `Walk::shuffle`).
+    pub shuffle: Option<u64>,
+}
+
+impl Report {
+    /// Whether nothing failed.
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty() && self.files.iter().all(|f| f.failures.is_empty())
+    }
+
+    /// Every failure, formatted the way `walk` reports them.
+    pub fn failures(&self) -> impl Iterator<Item = &str> {
+        self.errors.iter().map(String::as_str).chain(
+            self.files
+                .iter()
+                .flat_map(|f| f.failures.iter().map(String::as_str)),
+        )
+    }
+
+    /// The `n` test cases that took the longest, slowest first, along with the files they are in.
+    pub fn slowest_cases(&self, n: usize) -> Vec<(&FileReport, &CaseResult)> {
+        let mut cases: Vec<_> = self.cases().collect();
+        cases.sort_by_key(|(_, case)| std::cmp::Reverse(case.duration));
+        cases.truncate(n);
+        cases
+    }
+
+    /// The `n` files that took the longest, slowest first.
+    pub fn slowest_files(&self, n: usize) -> Vec<&FileReport> {
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_by_key(|file| std::cmp::Reverse(file.duration));
+        files.truncate(n);
+        files
+    }
+
+    /// Totals for the whole walk.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            files: self.files.len(),
+            duration: self.duration,
+            ..Summary::default()
+        };
+        for (_, case) in self.cases() {
+            summary.cases += 1;
+            match case.outcome {
+                Outcome::Passed => summary.passed += 1,
+                Outcome::Failed(_) | Outcome::Errored(_) => summary.failed += 1,
+                Outcome::Skipped => summary.skipped += 1,
+                Outcome::Rewritten(_) => summary.rewritten += 1,
+            }
+        }
+        summary
+    }
+
+    /// Every test case in every file, along with the file it is in.
+    pub fn cases(&self) -> impl Iterator<Item = (&FileReport, &CaseResult)> {
+        self.files
+            .iter()
+            .flat_map(|f| f.cases.iter().map(move |case| (f, case)))
+    }
+}
+
+/// What happened in one test file.
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    /// The name of the file.
+    pub filename: String,
+    /// Each test case in the file, from the last time it was run. Empty if it never was.
+    pub cases: Vec<CaseResult>,
+    /// Everything that went wrong in the file, formatted the way `walk` reports it. This includes
+    /// failures that don't belong to any one test case, like lint failures, or a panic outside of
+    /// a test case.
+    pub failures: Vec<String>,
+    /// Whether the file was rewritten with new expected output.
+    pub rewritten: bool,
+    /// How long the file took to run.
+    pub duration: Duration,
+}
+
+impl FileReport {
+    pub(crate) fn new(tf: TestFile, duration: Duration) -> Self {
+        FileReport {
+            filename: tf.filename,
+            cases: tf.results,
+            failures: tf.failures.messages,
+            rewritten: tf.rewrote,
+            duration,
+        }
+    }
+
+    // A file that failed without any test case results, because it couldn't be loaded, or never
+    // made it back to us after running it panicked.
+    pub(crate) fn lost(filename: String, failure: String, duration: Duration) -> Self {
+        FileReport {
+            filename,
+            cases: Vec::new(),
+            failures: vec![failure],
+            rewritten: false,
+            duration,
+        }
+    }
+}
+
+/// Totals for a walk, from `Report::summary`. Its `Display` is the one-line summary `walk` prints
+/// at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Summary {
+    /// How many files were run.
+    pub files: usize,
+    /// How many test cases there were in those files, whether or not they ran.
+    pub cases: usize,
+    /// How many test cases passed.
+    pub passed: usize,
+    /// How many test cases failed or errored.
+    pub failed: usize,
+    /// How many test cases were skipped.
+    pub skipped: usize,
+    /// How many test cases were rewritten with new expected output.
+    pub rewritten: usize,
+    /// How long the walk took.
+    pub duration: Duration,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "datadriven: {} file{}, {} test case{}: {} passed, {} failed, {} skipped, {} rewritten; \
+             finished in {:.2}s",
+            self.files,
+            if self.files == 1 { "" } else { "s" },
+            self.cases,
+            if self.cases == 1 { "" } else { "s" },
+            self.passed,
+            self.failed,
+            self.skipped,
+            self.rewritten,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+/// What happened to a test case when its file was run. See `TestFile::results`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    /// The line the test case starts on.
+    pub line: usize,
+    /// The test case's directive.
+    pub directive: String,
+    /// The test case's input.
+    pub input: String,
+    /// The output the test case was expected to produce.
+    pub expected: String,
+    /// The line the expected output starts on, or the line the test case starts on if it has no
+    /// expected output block.
+    pub expected_line: usize,
+    /// How the test case did.
+    pub outcome: Outcome,
+    /// How long the test case took to run.
+    pub duration: Duration,
+}
+
+impl CaseResult {
+    pub(crate) fn skipped(case: &TestCase) -> Self {
+        CaseResult {
+            line: case.line_number,
+            directive: case.directive.clone(),
+            input: case.input.clone(),
+            expected: case.expected.clone(),
+            expected_line: case.layout.expected_line().unwrap_or(case.line_number),
+            outcome: Outcome::Skipped,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// How a test case did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// It produced the expected output.
+    Passed,
+    /// It produced some other output, which this holds.
+    Failed(String),
+    /// It returned an error, panicked or timed out. This holds what went wrong.
+    Errored(String),
+    /// It produced some other output, which this holds, and it was rewritten to expect that
+    /// output (see `RewriteMode`). If the file ends up being left alone after all, because a later
+    /// test case errored or the file couldn't be written, it's `Failed` in the file's results.
+    Rewritten(String),
+    /// It wasn't run, or its output wasn't compared.
+    Skipped,
+}
+
+impl Outcome {
+    // Compares the output of a test case against what was expected.
+    pub(crate) fn of(case: &TestCase, result: Result<String, String>) -> Self {
+        match result {
+            Ok(output) if output == case.expected => Outcome::Passed,
+            Ok(output) => Outcome::Failed(output),
+            Err(err) => Outcome::Errored(err),
+        }
+    }
+
+    // What a test case that produced this outcome amounts to when its file is being rewritten.
+    pub(crate) fn rewritten_by(self, mode: RewriteMode) -> Self {
+        match self {
+            Outcome::Failed(output) if mode != RewriteMode::Check => Outcome::Rewritten(output),
+            outcome => outcome,
+        }
+    }
+
+    pub(crate) fn panicked(payload: &(dyn Any + Send)) -> Self {
+        Outcome::Errored(format!("panicked: {}", panic_message(payload)))
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn timed_out(timeout: Duration) -> Self {
+        Outcome::Errored(format!("timed out after {:?}", timeout))
+    }
+}
@@ -0,0 +1,122 @@
+//! Hooks for following a walk as it runs, for progress bars, metrics or custom summaries.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{capture, CaseResult, FileReport, Report, TestCase};
+
+/// Something that gets told about a walk as it happens. Attach one with `Walk::reporter`.
+///
+/// Every method does nothing by default, so a reporter only needs to implement the ones it cares
+/// about. Files can run on several threads at once (see `Walk::run_parallel`), so reporters are
+/// shared between them, and need to synchronize any state they keep themselves.
+///
+/// ```no_run
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use datadriven::{CaseResult, Outcome, Reporter, Walk};
+///
+/// #[derive(Default)]
+/// struct Dots(AtomicUsize);
+///
+/// impl Reporter for Dots {
+///     fn on_case_result(&self, _filename: &str, case: &CaseResult) {
+///         if case.outcome == Outcome::Passed {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///             eprint!(".");
+///         }
+///     }
+/// }
+///
+/// Walk::new("tests/testdata")
+///     .reporter(Dots::default())
+///     .run(|f| f.run(|case| case.input.clone()));
+/// ```
+pub trait Reporter: Send + Sync {
+    /// A file is about to run.
+    fn on_file_start(&self, _filename: &str) {}
+
+    /// A test case in `filename` is about to run. `TestCase::line` says which one.
+    fn on_case_start(&self, _filename: &str, _case: &TestCase) {}
+
+    /// A test case in `filename` has run, or was skipped after starting because a filter only ran
+    /// it to build up state.
+    fn on_case_result(&self, _filename: &str, _case: &CaseResult) {}
+
+    /// `filename` was rewritten with `contents`.
+    fn on_rewrite(&self, _filename: &str, _contents: &str) {}
+
+    /// A file has finished running.
+    fn on_file_finish(&self, _file: &FileReport) {}
+
+    /// The walk is done. This is called once everything else has been reported.
+    fn on_finish(&self, _report: &Report) {}
+}
+
+/// The reporter `walk`, `Walk::run` and the rest of the panicking walks finish with, after any
+/// others. It prints the walk's `Summary` to stderr, and then if anything failed, it panics with
+/// every failure (and the seed, if the files were shuffled), so that the test fails. Walks that
+/// return a `Report` leave it out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PanicOnFailure;
+
+impl Reporter for PanicOnFailure {
+    fn on_finish(&self, report: &Report) {
+        capture::eprintln(format_args!("{}", report.summary()));
+        if !report.passed() {
+            let mut msg = String::new();
+            for f in report.failures() {
+                msg.push_str(f);
+                msg.push('\n');
+            }
+            if let Some(seed) = report.shuffle {
+                msg.push_str(&format!(
+                    "test files were shuffled; DATADRIVEN_SHUFFLE={} runs them in the same order\n",
+                    seed
+                ));
+            }
+            panic!("{}", msg);
+        }
+    }
+}
+
+// The reporters attached to a walk, which are told everything in the order they were attached.
+#[derive(Clone, Default)]
+pub(crate) struct Reporters(Vec<Arc<dyn Reporter>>);
+
+impl Reporters {
+    pub(crate) fn push<R: Reporter + 'static>(&mut self, reporter: R) {
+        self.0.push(Arc::new(reporter));
+    }
+}
+
+impl fmt::Debug for Reporters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Reporters({})", self.0.len())
+    }
+}
+
+impl Reporter for Reporters {
+    fn on_file_start(&self, filename: &str) {
+        self.0.iter().for_each(|r| r.on_file_start(filename));
+    }
+
+    fn on_case_start(&self, filename: &str, case: &TestCase) {
+        self.0.iter().for_each(|r| r.on_case_start(filename, case));
+    }
+
+    fn on_case_result(&self, filename: &str, case: &CaseResult) {
+        self.0.iter().for_each(|r| r.on_case_result(filename, case));
+    }
+
+    fn on_rewrite(&self, filename: &str, contents: &str) {
+        self.0.iter().for_each(|r| r.on_rewrite(filename, contents));
+    }
+
+    fn on_file_finish(&self, file: &FileReport) {
+        self.0.iter().for_each(|r| r.on_file_finish(file));
+    }
+
+    fn on_finish(&self, report: &Report) {
+        self.0.iter().for_each(|r| r.on_finish(report));
+    }
+}
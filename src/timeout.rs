@@ -0,0 +1,143 @@
+//! Timeouts for test cases, so a hung test case points at itself rather than hanging the whole
+//! test binary.
+
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{DataDrivenError, TestCase};
+
+/// Parses a duration like `500ms`, `5s` or `1.5m`.
+pub(crate) fn parse_duration(s: &str) -> Result<Duration, DataDrivenError> {
+    let (num, scale) = if let Some(num) = s.strip_suffix("ms") {
+        (num, 0.001)
+    } else if let Some(num) = s.strip_suffix('s') {
+        (num, 1.0)
+    } else if let Some(num) = s.strip_suffix('m') {
+        (num, 60.0)
+    } else {
+        Err(DataDrivenError::Argument(format!(
+            "duration {} needs a unit of ms, s or m",
+            s
+        )))?
+    };
+    match num.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n * scale)),
+        _ => Err(DataDrivenError::Argument(format!(
+            "couldn't parse duration: {}",
+            s
+        ))),
+    }
+}
+
+// Calls a function if it isn't dropped before a duration passes.
+pub(crate) struct Timer {
+    // Dropping this wakes the timer thread up so it can exit without firing.
+    _cancel: mpsc::Sender<()>,
+}
+
+impl Timer {
+    pub(crate) fn start<F>(duration: Duration, on_expire: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(duration) {
+                on_expire();
+            }
+        });
+        Timer { _cancel: cancel }
+    }
+}
+
+// A synchronous test case can't be interrupted, so the best we can do if one hangs is to say
+// which one it was and bring the process down.
+pub(crate) fn watchdog(duration: Duration, filename: &str, case: &TestCase) -> Timer {
+    let msg = format!(
+        "test case timed out after {:?}:\n{}:{}:\n{}",
+        duration, filename, case.line_number, case.input
+    );
+    Timer::start(duration, move || {
+        // Not `eprintln!`, since the test harness might be capturing that, and nothing captured
+        // would survive the abort.
+        let _ = writeln!(std::io::stderr(), "{}", msg);
+        std::process::abort();
+    })
+}
+
+/// Runs `future` to completion, or gives up on it and returns None if it takes longer than
+/// `timeout`.
+#[cfg(feature = "async")]
+pub(crate) async fn with_timeout<T>(future: T, timeout: Option<Duration>) -> Option<T::Output>
+where
+    T: Future,
+{
+    use futures::future::{select, Either};
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Some(future.await),
+    };
+    match select(Box::pin(future), Delay::new(timeout)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct DelayState {
+    expired: bool,
+    waker: Option<Waker>,
+}
+
+// A future that completes after a duration, without depending on any particular runtime.
+#[cfg(feature = "async")]
+struct Delay {
+    state: Arc<Mutex<DelayState>>,
+    _timer: Timer,
+}
+
+#[cfg(feature = "async")]
+impl Delay {
+    fn new(duration: Duration) -> Self {
+        let state = Arc::new(Mutex::new(DelayState::default()));
+        let expired = state.clone();
+        let timer = Timer::start(duration, move || {
+            let mut state = expired.lock().unwrap();
+            state.expired = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Delay {
+            state,
+            _timer: timer,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.expired {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
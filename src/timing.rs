@@ -0,0 +1,77 @@
+//! Finding where the time in a walk goes: warnings for slow test cases, and a report of the
+//! slowest test cases and files at the end.
+
+use std::env;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::{capture, CaseResult, Report, Reporter};
+
+/// Warns about each test case that takes longer than a threshold.
+pub(crate) struct SlowCases(pub(crate) Duration);
+
+impl SlowCases {
+    /// The threshold in `DATADRIVEN_SLOW_MS`, if it is set.
+    pub(crate) fn from_env() -> Option<Self> {
+        number_from_env("DATADRIVEN_SLOW_MS").map(|ms| SlowCases(Duration::from_millis(ms)))
+    }
+}
+
+impl Reporter for SlowCases {
+    fn on_case_result(&self, filename: &str, case: &CaseResult) {
+        if case.duration > self.0 {
+            capture::eprintln(format_args!(
+                "warning: {}:{}: {} took {:.3}s, more than {:?}",
+                filename,
+                case.line,
+                case.directive,
+                case.duration.as_secs_f64(),
+                self.0
+            ));
+        }
+    }
+}
+
+/// Prints the slowest test cases and files once the walk is done.
+pub(crate) struct Slowest(pub(crate) usize);
+
+impl Slowest {
+    /// How many to print from `DATADRIVEN_SLOWEST`, if it is set.
+    pub(crate) fn from_env() -> Option<Self> {
+        number_from_env("DATADRIVEN_SLOWEST").map(|n| Slowest(n as usize))
+    }
+}
+
+impl Reporter for Slowest {
+    fn on_finish(&self, report: &Report) {
+        let mut msg = String::from("datadriven: slowest test cases:");
+        for (file, case) in report.slowest_cases(self.0) {
+            let _ = write!(
+                msg,
+                "\n  {:>8.3}s {}:{} {}",
+                case.duration.as_secs_f64(),
+                file.filename,
+                case.line,
+                case.directive
+            );
+        }
+        msg.push_str("\ndatadriven: slowest files:");
+        for file in report.slowest_files(self.0) {
+            let _ = write!(
+                msg,
+                "\n  {:>8.3}s {}",
+                file.duration.as_secs_f64(),
+                file.filename
+            );
+        }
+        capture::eprintln(format_args!("{}", msg));
+    }
+}
+
+fn number_from_env(var: &str) -> Option<u64> {
+    let value = env::var(var).ok().filter(|v| !v.is_empty())?;
+    match value.parse() {
+        Ok(n) => Some(n),
+        Err(err) => panic!("{} is not a valid number: {}", var, err),
+    }
+}
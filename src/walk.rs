@@ -0,0 +1,670 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::env;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "async")]
+use futures::future::{BoxFuture, Future, FutureExt};
+
+use crate::events::Events;
+use crate::filter::CaseFilter;
+use crate::junit::JUnit;
+use crate::lint::lint_stanzas;
+use crate::reporter::Reporters;
+use crate::timing::{SlowCases, Slowest};
+use crate::{
+    capture, file_list, panic_message, Config, FileReport, Outcome, PanicOnFailure, Report,
+    Reporter, RewriteMode, TestFile,
+};
+
+/// What a walk does with the warnings from `datadriven::lint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LintLevel {
+    /// Don't lint test files.
+    #[default]
+    Allow,
+    /// Print lint warnings, but don't fail because of them.
+    Warn,
+    /// Treat lint warnings as test failures.
+    Deny,
+}
+
+/// A configurable walk over a directory of test files. `walk` and friends are shorthands for the
+/// default configuration.
+///
+/// ```no_run
+/// use datadriven::{LintLevel, Walk};
+///
+/// Walk::new("tests/testdata")
+///     .exclude(|f| f.filename.contains("slow"))
+///     .lints(LintLevel::Deny)
+///     .run(|f| f.run(|case| case.input.clone()));
+/// ```
+pub struct Walk<'a> {
+    dir: String,
+    exclusion_matcher: Box<dyn Fn(&TestFile) -> bool + 'a>,
+    lints: LintLevel,
+    allow_empty: bool,
+    shuffle: Option<u64>,
+    config: Config,
+}
+
+impl<'a> Walk<'a> {
+    /// Creates a walk over the test files in `dir` (or just `dir`, if it is a file).
+    pub fn new(dir: &str) -> Self {
+        let walk = Walk {
+            dir: dir.to_string(),
+            exclusion_matcher: Box::new(|_| false),
+            lints: LintLevel::default(),
+            allow_empty: false,
+            shuffle: shuffle_from_env(),
+            config: Config {
+                filter: CaseFilter::from_env(dir),
+                ..Config::default()
+            },
+        };
+        let walk = match env::var_os("DATADRIVEN_EVENTS") {
+            Some(path) => walk.events(path),
+            None => walk,
+        };
+        let walk = match env::var_os("DATADRIVEN_JUNIT") {
+            Some(path) => walk.junit(path),
+            None => walk,
+        };
+        let walk = match SlowCases::from_env() {
+            Some(slow) => walk.reporter(slow),
+            None => walk,
+        };
+        match Slowest::from_env() {
+            Some(slowest) => walk.reporter(slowest),
+            None => walk,
+        }
+    }
+
+    /// Skips any files for which `exclusion_matcher` returns true.
+    pub fn exclude<M>(mut self, exclusion_matcher: M) -> Self
+    where
+        M: Fn(&TestFile) -> bool + 'a,
+    {
+        self.exclusion_matcher = Box::new(exclusion_matcher);
+        self
+    }
+
+    /// Sets whether files are linted before they are run, and what happens to the warnings.
+    pub fn lints(mut self, level: LintLevel) -> Self {
+        self.lints = level;
+        self
+    }
+
+    /// Sets how many test cases in a file can fail in a row before the rest of the file is skipped.
+    /// `Some(1)` stops at the first failure, and `None` always runs the whole file. Defaults to
+    /// `Some(3)`.
+    ///
+    /// Panics on `Some(0)`, since a file can't be skipped before anything in it has failed.
+    pub fn max_consecutive_failures(mut self, max: Option<usize>) -> Self {
+        assert!(
+            max != Some(0),
+            "max_consecutive_failures must be at least 1, or None to never skip the rest of a file"
+        );
+        self.config.max_consecutive_failures = max;
+        self
+    }
+
+    /// Sets whether and how test files are rewritten with their actual output. Defaults to what the
+    /// `REWRITE` environment variable asks for.
+    pub fn rewrite(mut self, mode: Option<RewriteMode>) -> Self {
+        self.config.rewrite = mode;
+        self
+    }
+
+    /// When a test case returns an `Err`, treat it as the output `error: <err>` rather than as a
+    /// failure. This lets rewrites record expected errors, so that negative tests can be written
+    /// the same way as any other.
+    pub fn errors_as_output(mut self, errors_as_output: bool) -> Self {
+        self.config.errors_as_output = errors_as_output;
+        self
+    }
+
+    /// Fails any test case that takes longer than `timeout`. A test case can set its own timeout
+    /// with a `timeout` argument that is a duration with a unit, like `timeout=5s`. Any other
+    /// `timeout` argument, like `timeout=5`, is left for the handler.
+    ///
+    /// An async test case that times out is dropped, and the rest of its file is skipped. A
+    /// synchronous test case can't be interrupted, so each one with a timeout is watched by a
+    /// thread of its own, and if it times out, it gets reported and the whole process is aborted,
+    /// along with every other test running in it.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Lets the walk pass without running anything. By default, a walk fails if it finds no test
+    /// files, or none of its test cases run, since that usually means a directory was renamed.
+    /// Walks where `RUN`, `DATADRIVEN_DIRECTIVE` or `DATADRIVEN_CASE` pick out test cases can run
+    /// none of them, since those filters apply to every walk in the binary.
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    /// Runs files in an order shuffled by `seed`, rather than in natural order (where `file2`
+    /// comes before `file10`). The same seed always gives the same order, so a failure that only
+    /// happens in one order can be reproduced. This shakes out files that depend on state left
+    /// behind by the files before them.
+    ///
+    /// Setting the `DATADRIVEN_SHUFFLE` environment variable to a seed does this for every walk,
+    /// and setting it to `random` picks a seed for the whole process, which gets printed. The seed
+    /// is also in the `Report`, and in the panic message of a walk that fails.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.shuffle = Some(seed);
+        self
+    }
+
+    /// Tells `reporter` about the walk as it runs, after any reporters attached before it.
+    pub fn reporter<R: Reporter + 'static>(mut self, reporter: R) -> Self {
+        self.config.reporters.push(reporter);
+        self
+    }
+
+    /// Warns about every test case that takes longer than `threshold`. Setting the
+    /// `DATADRIVEN_SLOW_MS` environment variable to a number of milliseconds does this for every
+    /// walk. Warnings go straight to stderr, so libtest doesn't hide them for passing tests.
+    pub fn warn_slower_than(self, threshold: Duration) -> Self {
+        self.reporter(SlowCases(threshold))
+    }
+
+    /// Prints the `n` slowest test cases and files to stderr once the walk is done, even if libtest
+    /// is capturing output. Setting the `DATADRIVEN_SLOWEST` environment variable to a number does
+    /// this for every walk.
+    pub fn report_slowest(self, n: usize) -> Self {
+        self.reporter(Slowest(n))
+    }
+
+    /// Writes a JUnit XML report to `path` once the walk is done. Setting the `DATADRIVEN_JUNIT`
+    /// environment variable to a path does this for every walk.
+    ///
+    /// Each file is a `<testsuite>` and each test case a `<testcase>` named `directive@line`.
+    /// Walks that write to the same path add to the report, rather than replacing it, and so do
+    /// the other test binaries `cargo test` runs, even at the same time: a report that is already
+    /// there keeps its suites for any files that aren't run again. Writers take turns by locking a
+    /// `.lock` file next to the report.
+    pub fn junit<P: Into<PathBuf>>(self, path: P) -> Self {
+        self.reporter(JUnit(path.into()))
+    }
+
+    /// Writes a stream of events to `path` as the walk runs, one JSON object per line, or to stdout
+    /// if `path` is `-`. Setting the `DATADRIVEN_EVENTS` environment variable to a path does this
+    /// for every walk.
+    ///
+    /// Each event has an `event` field saying what it is: `file_started`, `case_started`,
+    /// `case_passed`, `case_failed`, `case_errored`, `case_skipped`, `case_rewritten`,
+    /// `file_rewritten`, `file_finished`, or `summary` once the walk is done. Events are appended
+    /// to the file, so walks that write to the same path, in this binary or the other test
+    /// binaries `cargo test` runs, add to the same stream. Delete the file first to start afresh.
+    pub fn events<P: AsRef<Path>>(self, path: P) -> Self {
+        let path = path.as_ref();
+        match Events::open(path) {
+            Ok(events) => self.reporter(events),
+            Err(err) => {
+                // A walk whose events can't be written still runs.
+                eprintln!(
+                    "warning: couldn't write events to {}: {}",
+                    path.display(),
+                    err
+                );
+                self
+            }
+        }
+    }
+
+    /// Runs `f` on each test file, panicking at the end if anything failed.
+    pub fn run<F>(self, f: F)
+    where
+        F: FnMut(&mut TestFile),
+    {
+        self.reporter(PanicOnFailure).try_run(f);
+    }
+
+    /// The same as `run`, but returns a report of what happened rather than panicking if anything
+    /// failed.
+    pub fn try_run<F>(self, mut f: F) -> Report
+    where
+        F: FnMut(&mut TestFile),
+    {
+        let started = Instant::now();
+        let list = match self.file_list() {
+            Ok(list) => list,
+            Err(err) => return self.abandon(err, started),
+        };
+        let mut files = Vec::new();
+        for file in list {
+            match self.open(&file) {
+                Some(Ok(tf)) => files.push(run_file(tf, &mut f)),
+                Some(Err(file)) => files.push(unreadable(file, &self.config.reporters)),
+                None => {}
+            }
+        }
+        self.done(files, started)
+    }
+
+    /// The same as `run`, but runs files on up to `threads` threads at once. Each file gets a
+    /// fresh handler from `make_handler`, so handlers don't need to be shareable between threads.
+    /// Failures are reported in the same order as `run` would report them.
+    pub fn run_parallel<M, H>(self, threads: usize, make_handler: M)
+    where
+        M: Fn() -> H + Sync,
+        H: FnMut(&mut TestFile),
+    {
+        self.reporter(PanicOnFailure)
+            .try_run_parallel(threads, make_handler);
+    }
+
+    /// The same as `run_parallel`, but returns a report of what happened rather than panicking if
+    /// anything failed.
+    pub fn try_run_parallel<M, H>(self, threads: usize, make_handler: M) -> Report
+    where
+        M: Fn() -> H + Sync,
+        H: FnMut(&mut TestFile),
+    {
+        let started = Instant::now();
+        // Files are opened here, since the exclusion matcher doesn't have to be shareable between
+        // threads either.
+        let queue: VecDeque<_> = match self.files() {
+            Ok(files) => files.into_iter().enumerate().collect(),
+            Err(err) => return self.abandon(err, started),
+        };
+        let files = Mutex::new(vec![None; queue.len()]);
+        let queue = Mutex::new(queue);
+        let reporters = &self.config.reporters;
+
+        thread::scope(|s| {
+            for _ in 0..threads.max(1) {
+                s.spawn(|| loop {
+                    let (idx, tf) = match queue.lock().unwrap().pop_front() {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let file = match tf {
+                        Ok(tf) => run_file(tf, make_handler()),
+                        Err(file) => unreadable(file, reporters),
+                    };
+                    files.lock().unwrap()[idx] = Some(file);
+                });
+            }
+        });
+        let files = files.into_inner().unwrap().into_iter().flatten().collect();
+        self.done(files, started)
+    }
+
+    /// The async equivalent of `run`. `f` must return the passed `TestFile`.
+    #[cfg(feature = "async")]
+    pub async fn run_async<F, T>(self, f: F)
+    where
+        F: FnMut(TestFile) -> T,
+        T: Future<Output = TestFile>,
+    {
+        self.reporter(PanicOnFailure).try_run_async(f).await;
+    }
+
+    /// The async equivalent of `try_run`.
+    #[cfg(feature = "async")]
+    pub async fn try_run_async<F, T>(self, mut f: F) -> Report
+    where
+        F: FnMut(TestFile) -> T,
+        T: Future<Output = TestFile>,
+    {
+        let started = Instant::now();
+        let list = match self.file_list() {
+            Ok(list) => list,
+            Err(err) => return self.abandon(err, started),
+        };
+        let mut files = Vec::new();
+        for file in list {
+            match self.open(&file) {
+                Some(Ok(tf)) => {
+                    let (filename, reporters) = (tf.filename.clone(), tf.config.reporters.clone());
+                    files.push(run_file_async(f(tf), filename, reporters).await);
+                }
+                Some(Err(file)) => files.push(unreadable(file, &self.config.reporters)),
+                None => {}
+            }
+        }
+        self.done(files, started)
+    }
+
+    /// The same as `run_async`, but runs up to `concurrency` files at once.
+    #[cfg(feature = "async")]
+    pub async fn run_async_concurrent<F, T>(self, concurrency: usize, f: F)
+    where
+        F: FnMut(TestFile) -> T,
+        T: Future<Output = TestFile>,
+    {
+        self.reporter(PanicOnFailure)
+            .try_run_async_concurrent(concurrency, f)
+            .await;
+    }
+
+    /// The same as `run_async_concurrent`, but returns a report of what happened rather than
+    /// panicking if anything failed.
+    #[cfg(feature = "async")]
+    pub async fn try_run_async_concurrent<F, T>(self, concurrency: usize, mut f: F) -> Report
+    where
+        F: FnMut(TestFile) -> T,
+        T: Future<Output = TestFile>,
+    {
+        use futures::StreamExt;
+
+        let started = Instant::now();
+        let files = match self.files() {
+            Ok(files) => files,
+            Err(err) => return self.abandon(err, started),
+        };
+        let reporters = &self.config.reporters;
+
+        // Create futures list so that we can execute them in parallel
+        let files = futures::stream::iter(files.into_iter().map(|tf| match tf {
+            Ok(tf) => {
+                let (filename, reporters) = (tf.filename.clone(), tf.config.reporters.clone());
+                run_file_async(f(tf), filename, reporters).left_future()
+            }
+            Err(file) => async move { unreadable(file, reporters) }.right_future(),
+        }))
+        .buffered(concurrency)
+        .collect()
+        .await;
+        self.done(files, started)
+    }
+
+    /// The same as `run_async_concurrent`, but each file runs as its own task, handed to `spawn`,
+    /// so that files actually run in parallel on a multi-threaded runtime. `spawn` is whatever the
+    /// runtime uses to spawn a task, like `tokio::spawn`; what it returns is ignored.
+    ///
+    /// ```no_run
+    /// # async fn example() {
+    /// datadriven::Walk::new("tests/testdata")
+    ///     .run_async_spawned(8, tokio::spawn, |mut f| async move {
+    ///         f.run_async(|case| async move { case.input }).await;
+    ///         f
+    ///     })
+    ///     .await;
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn run_async_spawned<F, T, S, J>(self, concurrency: usize, spawn: S, f: F)
+    where
+        F: FnMut(TestFile) -> T,
+        T: Future<Output = TestFile> + Send + 'static,
+        S: Fn(BoxFuture<'static, ()>) -> J,
+    {
+        self.reporter(PanicOnFailure)
+            .try_run_async_spawned(concurrency, spawn, f)
+            .await;
+    }
+
+    /// The same as `run_async_spawned`, but returns a report of what happened rather than
+    /// panicking if anything failed.
+    #[cfg(feature = "async")]
+    pub async fn try_run_async_spawned<F, T, S, J>(
+        self,
+        concurrency: usize,
+        spawn: S,
+        mut f: F,
+    ) -> Report
+    where
+        F: FnMut(TestFile) -> T,
+        T: Future<Output = TestFile> + Send + 'static,
+        S: Fn(BoxFuture<'static, ()>) -> J,
+    {
+        use futures::channel::oneshot;
+        use futures::StreamExt;
+
+        let started = Instant::now();
+        let files = match self.files() {
+            Ok(files) => files,
+            Err(err) => return self.abandon(err, started),
+        };
+        let walk_reporters = &self.config.reporters;
+
+        let spawn = &spawn;
+        let files = futures::stream::iter(files.into_iter().map(|tf| {
+            let tf = match tf {
+                Ok(tf) => tf,
+                Err(file) => return async move { unreadable(file, walk_reporters) }.right_future(),
+            };
+            let (filename, reporters) = (tf.filename.clone(), tf.config.reporters.clone());
+            let file = f(tf);
+            async move {
+                // Panics are caught inside the task, so they come back as failures for the file
+                // rather than however the runtime deals with them.
+                let (tx, rx) = oneshot::channel();
+                let started = Instant::now();
+                let task = run_file_async(file, filename.clone(), reporters.clone()).map(|file| {
+                    let _ = tx.send(file);
+                });
+                spawn(task.boxed());
+                rx.await.unwrap_or_else(|_| {
+                    let failure = format!(
+                        "failure:\n{}:\nthe task running the file was dropped\n",
+                        filename
+                    );
+                    let file = FileReport::lost(filename, failure, started.elapsed());
+                    reporters.on_file_finish(&file);
+                    file
+                })
+            }
+            .left_future()
+        }))
+        .buffered(concurrency)
+        .collect()
+        .await;
+        self.done(files, started)
+    }
+
+    // Reports on a walk that couldn't run at all, because of `error`.
+    pub(crate) fn abandon(&self, error: String, started: Instant) -> Report {
+        let report = Report {
+            files: Vec::new(),
+            errors: vec![format!("failure:\n{}\n", error)],
+            duration: started.elapsed(),
+            shuffle: self.shuffle,
+        };
+        self.config.reporters.on_finish(&report);
+        report
+    }
+
+    // Reports on the walk once it is done.
+    pub(crate) fn done(&self, files: Vec<FileReport>, started: Instant) -> Report {
+        let mut report = Report {
+            files,
+            errors: Vec::new(),
+            duration: started.elapsed(),
+            shuffle: self.shuffle,
+        };
+        if !self.allow_empty {
+            let ran = |outcome: &Outcome| *outcome != Outcome::Skipped;
+            if report.files.is_empty() {
+                report
+                    .errors
+                    .push(format!("failure:\nno test files found in {}\n", self.dir));
+            } else if self.config.filter.is_empty()
+                && !report.cases().any(|(_, case)| ran(&case.outcome))
+            {
+                // A case filter applies to every walk in the binary, so it's expected to leave
+                // some of them with nothing to run.
+                report
+                    .errors
+                    .push(format!("failure:\nno test cases ran in {}\n", self.dir));
+            }
+        }
+        self.config.reporters.on_finish(&report);
+        report
+    }
+
+    // Loads and lints every test file that should be run. Files that can't be loaded are reported
+    // as failed, and if the walk can't find its files at all, that's the error.
+    pub(crate) fn files(&self) -> Result<Vec<Result<TestFile, FileReport>>, String> {
+        Ok(self
+            .file_list()?
+            .iter()
+            .filter_map(|file| self.open(file))
+            .collect())
+    }
+
+    // A failed report standing in for the files of a walk that couldn't find them, because of
+    // `error`.
+    pub(crate) fn abandoned_file(&self, error: String) -> FileReport {
+        let failure = format!("failure:\n{}\n", error);
+        FileReport::lost(self.dir.clone(), failure, Duration::ZERO)
+    }
+
+    pub(crate) fn reporters(&self) -> &Reporters {
+        &self.config.reporters
+    }
+
+    // The test files to run, in the order to run them.
+    fn file_list(&self) -> Result<Vec<PathBuf>, String> {
+        if let Some(err) = self.config.filter.errors().first() {
+            return Err(err.clone());
+        }
+        let mut files = file_list(&self.dir)?;
+        if let Some(seed) = self.shuffle {
+            shuffle(&mut files, seed);
+        }
+        Ok(files)
+    }
+
+    // Loads and lints a test file, returning None if it shouldn't be run, and a failed report if it
+    // couldn't be read or parsed.
+    fn open(&self, file: &Path) -> Option<Result<TestFile, FileReport>> {
+        let mut tf = match TestFile::new(file) {
+            Ok(tf) => tf,
+            Err(err) => {
+                let filename = file.to_string_lossy().to_string();
+                let failure = format!("failure:\n{}\n", err);
+                return Some(Err(FileReport::lost(filename, failure, Duration::ZERO)));
+            }
+        };
+        if (self.exclusion_matcher)(&tf) {
+            return None;
+        }
+        if self.lints != LintLevel::Allow {
+            for warning in lint_stanzas(&tf.filename, &tf.stanzas) {
+                match self.lints {
+                    LintLevel::Deny => tf
+                        .failures
+                        .messages
+                        .push(format!("lint failure:\n{}\n", warning)),
+                    _ => eprintln!("warning: {}", warning),
+                }
+            }
+        }
+        tf.config = self.config.clone();
+        Some(Ok(tf))
+    }
+}
+
+// Runs `f` on a file.
+pub(crate) fn run_file<F>(mut tf: TestFile, mut f: F) -> FileReport
+where
+    F: FnMut(&mut TestFile),
+{
+    let reporters = tf.config.reporters.clone();
+    reporters.on_file_start(&tf.filename);
+    let started = Instant::now();
+    // Panics in test cases are caught by `TestFile::run`, but the rest of `f` can panic too.
+    let result = catch_unwind(AssertUnwindSafe(|| f(&mut tf)));
+    let mut file = FileReport::new(tf, started.elapsed());
+    if let Err(payload) = result {
+        file.failures
+            .push(file_panicked(&file.filename, payload.as_ref()));
+    }
+    reporters.on_file_finish(&file);
+    file
+}
+
+// Reports on a file that couldn't be loaded, which fails without running anything.
+pub(crate) fn unreadable(file: FileReport, reporters: &Reporters) -> FileReport {
+    reporters.on_file_start(&file.filename);
+    reporters.on_file_finish(&file);
+    file
+}
+
+// Runs the future for a whole file, turning a panic into a failure for that file.
+#[cfg(feature = "async")]
+async fn run_file_async<T>(future: T, filename: String, reporters: Reporters) -> FileReport
+where
+    T: Future<Output = TestFile>,
+{
+    reporters.on_file_start(&filename);
+    let started = Instant::now();
+    let result = AssertUnwindSafe(future).catch_unwind().await;
+    let file = match result {
+        Ok(tf) => FileReport::new(tf, started.elapsed()),
+        Err(payload) => {
+            let failure = file_panicked(&filename, payload.as_ref());
+            FileReport::lost(filename, failure, started.elapsed())
+        }
+    };
+    reporters.on_file_finish(&file);
+    file
+}
+
+// The seed `DATADRIVEN_SHUFFLE=random` picked. Every walk in the process uses the same one, so
+// that the one seed that gets printed reproduces the whole run.
+static RANDOM_SEED: OnceLock<u64> = OnceLock::new();
+
+// Reads the seed to shuffle files with from `DATADRIVEN_SHUFFLE`, if it is set.
+fn shuffle_from_env() -> Option<u64> {
+    let value = env::var("DATADRIVEN_SHUFFLE")
+        .ok()
+        .filter(|v| !v.is_empty())?;
+    if value == "random" {
+        return Some(*RANDOM_SEED.get_or_init(|| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let seed = now.as_nanos() as u64;
+            capture::eprintln(format_args!(
+                "datadriven: shuffling test files with DATADRIVEN_SHUFFLE={}",
+                seed
+            ));
+            seed
+        }));
+    }
+    match value.parse() {
+        Ok(seed) => Some(seed),
+        Err(_) => panic!(
+            "DATADRIVEN_SHUFFLE must be a number or `random`, not {}",
+            value
+        ),
+    }
+}
+
+// Shuffles `files` with a Fisher-Yates shuffle driven by splitmix64, which is plenty random for
+// putting files in an order nobody chose, and keeps the order the same everywhere for a seed.
+fn shuffle(files: &mut [PathBuf], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..files.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        files.swap(i, j);
+    }
+}
+
+fn file_panicked(filename: &str, payload: &(dyn Any + Send)) -> String {
+    format!(
+        "failure:\n{}:\npanicked: {}\n",
+        filename,
+        panic_message(payload)
+    )
+}
@@ -0,0 +1,19 @@
+//! Runs test files through `datadriven::harness`, the way a `harness = false` test target would.
+
+use datadriven::harness::{self, Harness};
+
+fn main() {
+    harness::main(
+        Harness::new()
+            .dir("tests/testdata_macro", |f| {
+                f.run(|s| format!("{}\n", s.input.trim()))
+            })
+            .dir("tests/testdata_async_state", |f| {
+                let mut total = 0;
+                f.run(|s| {
+                    total += s.take_arg::<i64>("n")?;
+                    Ok::<_, datadriven::DataDrivenError>(format!("{}\n", total))
+                })
+            }),
+    );
+}
@@ -1,5 +1,15 @@
-use datadriven::{walk, walk_async, walk_async_exclusive, walk_exclusive};
+use datadriven::fmt::{check_path, format, FormatOptions};
+use datadriven::lint::{lint_path, LintKind};
+use datadriven::{
+    try_walk, walk, walk_async, walk_async_exclusive, CaseResult, FileReport, LintLevel, Outcome,
+    Report, Reporter, RewriteMode, Summary, TestCase, Walk,
+};
+use futures::FutureExt;
 use std::cell::RefCell;
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 #[cfg(test)]
 mod tests {
@@ -164,7 +174,8 @@ mod tests {
             "tests/testdata/nested/nested_file".to_string(),
         ]);
 
-        walk("tests/testdata", |f| {
+        // None of the files are run, just listed.
+        Walk::new("tests/testdata").allow_empty(true).run(|f| {
             assert!(
                 filenames.remove(&f.filename),
                 "could not find {}",
@@ -178,18 +189,17 @@ mod tests {
     #[test]
     fn walk_excluded() {
         let excluded = RefCell::new(0);
-        walk_exclusive(
-            "tests/testdata",
-            |_| (),
-            |f| {
+        Walk::new("tests/testdata")
+            .allow_empty(true)
+            .exclude(|f| {
                 if f.filename.contains("excluded") {
                     *excluded.borrow_mut() += 1;
                     true
                 } else {
                     false
                 }
-            },
-        );
+            })
+            .run(|_| ());
         assert_eq!(*excluded.borrow(), 1);
     }
 
@@ -235,4 +245,1219 @@ mod tests {
         .await;
         assert_eq!(*excluded.borrow(), 1);
     }
+
+    #[test]
+    fn fmt() {
+        let contents = "\n\n  echo   a=1  b=(x,   y) c=()  # a comment  \nfoo\n----\nfoo\n\n\n\n# between\necho\nbar\n----\n----\nbar\n----\n----\n\n";
+        assert_eq!(
+            format(contents, &FormatOptions::default()).unwrap(),
+            "echo a=1 b=(x,y) c # a comment\nfoo\n----\nfoo\n\n# between\necho\nbar\n----\nbar\n",
+        );
+    }
+
+    #[test]
+    fn fmt_stray_line() {
+        // The line after a double-separator block is skipped by the parser, so formatting would
+        // delete it.
+        let err = format(
+            "echo\n----\n----\na\n\nb\n----\n----\nstray\n",
+            &FormatOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with(":9: "), "{}", err);
+    }
+
+    #[test]
+    fn fmt_sort_args() {
+        let opts = FormatOptions { sort_args: true };
+        assert_eq!(
+            format("echo c b=2 a=(1,2)\n----\n", &opts).unwrap(),
+            "echo a=(1,2) b=2 c\n----\n",
+        );
+    }
+
+    #[test]
+    fn fmt_idempotent() {
+        let opts = FormatOptions::default();
+        for file in ["tests/testdata", "tests/parsing"] {
+            assert!(check_path(Path::new(file), &opts).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn lint() {
+        let warnings = lint_path(Path::new("tests/lint/mistakes")).unwrap();
+        let found: Vec<_> = warnings.iter().map(|w| (w.line, w.kind)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (7, LintKind::SwallowedCase),
+                (14, LintKind::TrailingSeparator),
+                (25, LintKind::StrayText),
+                (27, LintKind::DuplicateCase),
+                (32, LintKind::MissingSeparator),
+            ]
+        );
+        assert_eq!(
+            warnings[3].to_string(),
+            "tests/lint/mistakes:27: same directive and input as the test case on line 1, but \
+             different expected output",
+        );
+
+        assert!(lint_path(Path::new("tests/testdata")).unwrap().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "tests/lint/mistakes:7: expected output contains a `----`")]
+    fn walk_deny_lints() {
+        Walk::new("tests/lint").lints(LintLevel::Deny).run(|_| ());
+    }
+
+    fn walk_failure(walk: Walk) -> String {
+        walk_failure_with(walk, |walk| {
+            walk.run(|f| {
+                f.run(|s| format!("{}\n", s.input.trim()));
+            })
+        })
+    }
+
+    // Runs a walk that's expected to fail, returning the failure message.
+    fn walk_failure_with<F>(walk: Walk, f: F) -> String
+    where
+        F: FnOnce(Walk),
+    {
+        let result = catch_unwind(AssertUnwindSafe(|| f(walk)));
+        *result.unwrap_err().downcast::<String>().unwrap()
+    }
+
+    #[test]
+    fn keep_going() {
+        let msg = walk_failure(Walk::new("tests/testdata_keep_going"));
+        assert!(msg.contains("cascade:1:"));
+        assert!(!msg.contains("cascade:6:"));
+        assert!(msg.contains("cascade:11:"));
+        assert!(msg.contains("cascade:21:"));
+        assert!(!msg.contains("cascade:26:"));
+        assert!(msg.contains("skipped the rest of the file after 3 consecutive failures"));
+
+        let msg =
+            walk_failure(Walk::new("tests/testdata_keep_going").max_consecutive_failures(Some(1)));
+        assert!(msg.contains("cascade:1:"));
+        assert!(!msg.contains("cascade:11:"));
+
+        let msg =
+            walk_failure(Walk::new("tests/testdata_keep_going").max_consecutive_failures(None));
+        assert!(msg.contains("cascade:26:"));
+        assert!(!msg.contains("skipped the rest of the file"));
+
+        let zero = catch_unwind(|| {
+            Walk::new("tests/testdata_keep_going").max_consecutive_failures(Some(0))
+        });
+        assert!(zero.is_err());
+    }
+
+    // Writes `contents` to a fresh file in a scratch directory for tests that modify their files.
+    fn scratch_file(name: &str, contents: &str) -> PathBuf {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("test");
+        fs::write(&file, contents).unwrap();
+        file
+    }
+
+    fn rewrite_echo(file: &Path, mode: RewriteMode) {
+        Walk::new(file.to_str().unwrap())
+            .rewrite(Some(mode))
+            .run(|f| f.run(|s| format!("{}\n", s.input.trim())));
+    }
+
+    #[test]
+    fn rewrite_failing() {
+        // The passing test cases aren't in the form a rewrite would produce, and should be left
+        // alone.
+        let contents = "echo   a=1\nfoo\n----\n----\nfoo\n----\n----\n\n\necho\nbar\n----\nwrong\n\n# comment\necho\nbaz\n----\nbaz";
+        let file = scratch_file("rewrite_failing", contents);
+        rewrite_echo(&file, RewriteMode::Failing);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            contents.replace("wrong", "bar"),
+        );
+
+        rewrite_echo(&file, RewriteMode::All);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "echo   a=1\nfoo\n----\nfoo\n\n\necho\nbar\n----\nbar\n\n# comment\necho\nbaz\n----\nbaz\n",
+        );
+    }
+
+    #[test]
+    fn rewrite_failing_no_separator() {
+        let file = scratch_file(
+            "rewrite_failing_no_separator",
+            "echo\nfoo\n----\nfoo\n\necho\nbar",
+        );
+        rewrite_echo(&file, RewriteMode::Failing);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "echo\nfoo\n----\nfoo\n\necho\nbar\n----\nbar\n",
+        );
+    }
+
+    #[test]
+    fn rewrite_outcomes() {
+        let rewrite = |file: &Path| {
+            Walk::new(file.to_str().unwrap())
+                .rewrite(Some(RewriteMode::Failing))
+                .try_run(|f| {
+                    f.run(|s| match s.directive.as_str() {
+                        "echo" => Ok(s.input.clone()),
+                        _ => Err("oops".to_string()),
+                    })
+                })
+        };
+        let outcomes = |report: &Report| -> Vec<Outcome> {
+            report.cases().map(|(_, c)| c.outcome.clone()).collect()
+        };
+
+        let file = scratch_file(
+            "rewrite_outcomes",
+            "echo\nfoo\n----\nfoo\n\necho\nbar\n----\nwrong\n",
+        );
+        let report = rewrite(&file);
+        assert!(report.passed());
+        assert!(report.files[0].rewritten);
+        assert_eq!(
+            outcomes(&report),
+            vec![Outcome::Passed, Outcome::Rewritten("bar\n".into())]
+        );
+        let summary = report.summary();
+        assert_eq!(
+            (summary.passed, summary.failed, summary.rewritten),
+            (1, 0, 1)
+        );
+
+        // A test case that errors leaves the file alone, so nothing was rewritten after all.
+        let contents = "echo\nbar\n----\nwrong\n\nfail\n----\n";
+        let file = scratch_file("rewrite_outcomes", contents);
+        let report = rewrite(&file);
+        assert!(!report.passed());
+        assert!(!report.files[0].rewritten);
+        assert_eq!(
+            outcomes(&report),
+            vec![
+                Outcome::Failed("bar\n".into()),
+                Outcome::Errored("oops".into())
+            ]
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), contents);
+    }
+
+    #[test]
+    fn rewrite_check() {
+        // This passes, but a rewrite would switch it to a single separator.
+        let contents = "echo\nfoo\n----\n----\nfoo\n----\n----\n";
+        let file = scratch_file("rewrite_check", contents);
+        let msg = walk_failure(Walk::new(file.to_str().unwrap()).rewrite(Some(RewriteMode::Check)));
+        assert!(msg.contains("rewriting would change the file"));
+        assert!(msg.contains("@@ -1,7 +1,4 @@"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), contents);
+
+        rewrite_echo(&file, RewriteMode::All);
+        rewrite_echo(&file, RewriteMode::Check);
+    }
+
+    fn parse_number(walk: Walk) {
+        walk.run(|f| f.run(|s| s.input.trim().parse::<u64>().map(|n| format!("{}\n", n))));
+    }
+
+    #[test]
+    fn errors_as_output() {
+        parse_number(Walk::new("tests/testdata_errors").errors_as_output(true));
+
+        let contents = "parse\nabc\n----\n";
+        let file = scratch_file("errors_as_output", contents);
+        let msg = walk_failure_with(
+            Walk::new(file.to_str().unwrap()).rewrite(Some(RewriteMode::All)),
+            parse_number,
+        );
+        assert!(msg.contains("invalid digit found in string"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), contents);
+
+        parse_number(
+            Walk::new(file.to_str().unwrap())
+                .rewrite(Some(RewriteMode::All))
+                .errors_as_output(true),
+        );
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "parse\nabc\n----\nerror: invalid digit found in string\n",
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rewrite_symlink() {
+        let file = scratch_file("rewrite_symlink", "echo\nfoo\n----\nbar\n");
+        let link = file.with_file_name("link");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        rewrite_echo(&link, RewriteMode::All);
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "echo\nfoo\n----\nfoo\n");
+    }
+
+    #[test]
+    fn rewrite_read_only() {
+        let contents = "echo\nfoo\n----\nbar\n";
+        let file = scratch_file("rewrite_read_only", contents);
+        let mut permissions = fs::metadata(&file).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file, permissions).unwrap();
+
+        let msg = walk_failure(Walk::new(file.to_str().unwrap()).rewrite(Some(RewriteMode::All)));
+        assert!(msg.contains(&format!(
+            "rewriting {}: io: file is read-only",
+            file.display()
+        )));
+        assert_eq!(fs::read_to_string(&file).unwrap(), contents);
+        assert_eq!(fs::read_dir(file.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn panics() {
+        let mut ran = Vec::new();
+        let msg = walk_failure_with(Walk::new("tests/testdata_panics"), |walk| {
+            walk.run(|f| {
+                f.run(|s| {
+                    ran.push(s.input.trim().to_string());
+                    if s.directive == "panic" {
+                        panic!("{}", s.input.trim());
+                    }
+                    format!("{}\n", s.input.trim())
+                })
+            })
+        });
+        assert!(msg.contains("tests/testdata_panics/panic:6:\nboom\n\npanicked: boom"));
+        // The rest of the file is skipped, but the next file still runs.
+        ran.sort();
+        assert_eq!(ran, vec!["a", "b", "boom"]);
+
+        let msg = walk_failure_with(Walk::new("tests/testdata_panics/then_this"), |walk| {
+            walk.run(|_| panic!("outside of a test case"))
+        });
+        assert!(msg.contains("tests/testdata_panics/then_this:\npanicked: outside of a test case"));
+    }
+
+    #[tokio::test]
+    async fn panics_async() {
+        let result = AssertUnwindSafe(Walk::new("tests/testdata_panics").run_async(
+            |mut f| async move {
+                f.run_async(|s| async move {
+                    if s.directive == "panic" {
+                        panic!("{}", s.input.trim());
+                    }
+                    format!("{}\n", s.input.trim())
+                })
+                .await;
+                f
+            },
+        ))
+        .catch_unwind()
+        .await;
+        let msg = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("tests/testdata_panics/panic:6:\nboom\n\npanicked: boom"));
+        assert!(!msg.contains("wrong"));
+    }
+
+    #[test]
+    fn timeout_arguments() {
+        // Only a duration is a timeout. Anything else is an argument like any other.
+        let file = scratch_file(
+            "timeout_arguments",
+            "wait timeout=5\n----\n[\"5\"]\n\nwait timeout=(a,b)\n----\n[\"a\", \"b\"]\n\nwait timeout=5s\n----\n[]\n",
+        );
+        walk(file.to_str().unwrap(), |f| {
+            f.run(|s| {
+                let timeout: Vec<String> = s.take_args("timeout").unwrap_or_default();
+                s.expect_empty().unwrap();
+                format!("{:?}\n", timeout)
+            })
+        });
+    }
+
+    #[tokio::test]
+    async fn timeout_async() {
+        let result = AssertUnwindSafe(Walk::new("tests/testdata_timeout").run_async(
+            |mut f| async move {
+                f.run_async(|s| async move {
+                    // The timeout argument is taken care of before we see the test case.
+                    s.expect_empty().unwrap();
+                    if s.directive == "hang" {
+                        futures::future::pending::<()>().await;
+                    }
+                    format!("{}\n", s.input.trim())
+                })
+                .await;
+                f
+            },
+        ))
+        .catch_unwind()
+        .await;
+        let msg = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("tests/testdata_timeout/hang:6:\n\ntimed out after 50ms"));
+    }
+
+    // Run by `timeout_sync`, since it brings down the whole process.
+    #[test]
+    #[ignore]
+    fn timeout_sync_child() {
+        walk("tests/testdata_timeout", |f| {
+            f.run(|s| {
+                if s.directive == "hang" {
+                    std::thread::sleep(std::time::Duration::from_secs(60));
+                }
+                format!("{}\n", s.input.trim())
+            })
+        });
+    }
+
+    #[test]
+    fn timeout_sync() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--ignored", "--exact", "tests::timeout_sync_child"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("test case timed out after 50ms:\ntests/testdata_timeout/hang:6:"),
+            "{}",
+            stderr
+        );
+    }
+
+    // Run by `case_filters` with different filters in its environment.
+    #[test]
+    #[ignore]
+    fn case_filter_child() {
+        walk("tests/testdata_filter", |f| {
+            let mut total = 0;
+            f.run(|s| match s.directive.as_str() {
+                "add" => {
+                    total += s.input.trim().parse::<i64>().unwrap();
+                    format!("{}\n", total)
+                }
+                "read" => format!("{}\n", total),
+                _ => "not what was expected\n".to_string(),
+            })
+        });
+    }
+
+    #[test]
+    fn case_filters() {
+        let run = |vars: &[(&str, &str)]| {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--ignored", "--exact", "tests::case_filter_child"])
+                .env_remove("RUN")
+                .env_remove("DATADRIVEN_DIRECTIVE")
+                .env_remove("DATADRIVEN_CASE")
+                .env_remove("REWRITE")
+                .envs(vars.iter().copied())
+                .output()
+                .unwrap();
+            (
+                output.status.success(),
+                String::from_utf8_lossy(&output.stdout).to_string(),
+            )
+        };
+
+        let (ok, stdout) = run(&[]);
+        assert!(!ok);
+        assert!(
+            stdout.contains("tests/testdata_filter/counter:11:"),
+            "{}",
+            stdout
+        );
+
+        // The test cases before the selected one still run, since the selected one depends on
+        // them, but the failing one isn't compared.
+        assert!(run(&[("RUN", "counter:15")]).0);
+        assert!(run(&[("RUN", "counter/read")]).0);
+        assert!(run(&[("RUN", "counter/add@6")]).0);
+        assert!(run(&[("DATADRIVEN_DIRECTIVE", "^(add|read)$")]).0);
+        assert!(run(&[("DATADRIVEN_CASE", "(?m)^4$")]).0);
+        // Filters apply to every walk in the binary, so a walk with nothing that matches passes.
+        assert!(run(&[("DATADRIVEN_DIRECTIVE", "^nothing$")]).0);
+
+        let (ok, stdout) = run(&[("RUN", "counter/wrong")]);
+        assert!(!ok);
+        assert!(
+            stdout.contains("tests/testdata_filter/counter:11:"),
+            "{}",
+            stdout
+        );
+        assert!(!run(&[("RUN", "counter:11")]).0);
+    }
+
+    // Run by `run_narrowing` with different values of `RUN`.
+    #[test]
+    #[ignore]
+    fn run_narrowing_child() {
+        Walk::new("tests/testdata")
+            .allow_empty(true)
+            .run(|f| println!("ran {}", f.filename));
+    }
+
+    #[test]
+    fn run_narrowing() {
+        let run = |pattern: &str| {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--ignored", "--exact", "--nocapture"])
+                .arg("tests::run_narrowing_child")
+                .env("RUN", pattern)
+                .output()
+                .unwrap();
+            let mut ran: Vec<String> = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                // The first file ends up on the same line as libtest's own output.
+                .filter_map(|l| l.split("ran tests/testdata/").nth(1))
+                .map(String::from)
+                .collect();
+            ran.sort();
+            let unmatched = String::from_utf8_lossy(&output.stderr)
+                .contains("doesn't match any test files in tests/testdata");
+            assert_eq!(output.status.success(), !unmatched);
+            (output.status.success(), ran)
+        };
+
+        assert_eq!(run("nested"), (true, vec!["nested/nested_file".into()]));
+        assert_eq!(run("args"), (true, vec!["args".into()]));
+        assert_eq!(
+            run("*line"),
+            (true, vec!["multiline".into(), "nonewline".into()])
+        );
+        assert_eq!(run("**/*_file"), (true, vec!["nested/nested_file".into()]));
+        assert_eq!(
+            run("re:^n"),
+            (true, vec!["nested/nested_file".into(), "nonewline".into()])
+        );
+
+        // Typos fail rather than quietly running nothing, even with `allow_empty`.
+        assert_eq!(run("nestde"), (false, vec![]));
+        assert_eq!(run("*.txt"), (false, vec![]));
+
+        // A missing directory still fails, and a walk of a single file still runs it.
+        let child = |test: &str| {
+            std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--ignored", "--exact", test])
+                .env("RUN", "nested")
+                .output()
+                .unwrap()
+        };
+        let output = child("tests::run_missing_child");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("no test files found"));
+        assert!(child("tests::run_single_file_child").status.success());
+    }
+
+    // Run by `run_narrowing` with `RUN` set.
+    #[test]
+    #[ignore]
+    fn run_missing_child() {
+        walk("tests/does_not_exist", |f| f.run(|s| s.input.clone()));
+    }
+
+    // Run by `run_narrowing` with `RUN` set.
+    #[test]
+    #[ignore]
+    fn run_single_file_child() {
+        walk("tests/testdata_macro/simple", |f| {
+            f.run(|s| format!("{}\n", s.input.trim()))
+        });
+    }
+
+    #[tokio::test]
+    async fn errors_async() {
+        let parse = |walk: Walk<'static>| {
+            walk.run_async(|mut f| async move {
+                f.run_async(|s| async move {
+                    s.input.trim().parse::<i64>().map(|n| format!("{}\n", n))
+                })
+                .await;
+                f
+            })
+        };
+        parse(Walk::new("tests/testdata_errors").errors_as_output(true)).await;
+
+        let result = AssertUnwindSafe(parse(Walk::new("tests/testdata_errors")))
+            .catch_unwind()
+            .await;
+        let msg = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(
+            msg.contains("tests/testdata_errors/parse:6:\nabc\n\ninvalid digit found in string"),
+            "{}",
+            msg
+        );
+    }
+
+    #[tokio::test]
+    async fn run_async_with() {
+        let sum = |walk: Walk<'static>| {
+            walk.run_async(|mut f| async move {
+                let mut total = 0;
+                f.run_async_with(&mut total, |total, s| {
+                    async move {
+                        let n: i64 = s.take_arg("n")?;
+                        s.expect_empty()?;
+                        *total += n;
+                        Ok::<_, datadriven::DataDrivenError>(format!("{}\n", total))
+                    }
+                    .boxed()
+                })
+                .await;
+                f
+            })
+        };
+        sum(Walk::new("tests/testdata_async_state")).await;
+
+        // Arguments the handler didn't take are reported, since it gets the test case itself.
+        let file = scratch_file("run_async_with", "add n=1 extra\n----\n1\n");
+        let result = AssertUnwindSafe(sum(Walk::new(file.to_str().unwrap())))
+            .catch_unwind()
+            .await;
+        let msg = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("run_async_with/test:1:"), "{}", msg);
+        assert!(msg.contains("extra"), "{}", msg);
+    }
+
+    #[test]
+    fn rerun() {
+        let report = Walk::new("tests/testdata_async_state")
+            .max_consecutive_failures(Some(2))
+            .try_run(|f| {
+                // Only the first run is off by one, so it's the only one that fails.
+                for start in [1, 0] {
+                    let mut total = start;
+                    f.run(|s| {
+                        total += s.take_arg::<i64>("n").unwrap();
+                        format!("{}\n", total)
+                    });
+                }
+            });
+        // The second run passing doesn't make up for the first failing.
+        assert!(!report.passed());
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 3, "{:?}", failures);
+        assert!(failures[0].contains("sum:1:"), "{:?}", failures);
+        assert!(failures[2].contains("skipped the rest of the file after 2 consecutive failures"));
+        // The results are from the last run, which passed.
+        assert!(report
+            .cases()
+            .all(|(_, case)| case.outcome == Outcome::Passed));
+    }
+
+    #[tokio::test]
+    async fn rerun_async() {
+        let results = RefCell::new(Vec::new());
+        let walk = Walk::new("tests/testdata_async_state").run_async(|mut f| {
+            let results = &results;
+            async move {
+                // The second run is off by one, so every test case fails.
+                for mut total in [0, 1] {
+                    f.run_async_with(&mut total, |total, s| {
+                        async move {
+                            *total += s.take_arg::<i64>("n").unwrap();
+                            format!("{}\n", total)
+                        }
+                        .boxed()
+                    })
+                    .await;
+                    results.borrow_mut().push(f.results().to_vec());
+                }
+                f
+            }
+        });
+        let msg = AssertUnwindSafe(walk).catch_unwind().await.unwrap_err();
+        assert!(msg.downcast::<String>().unwrap().contains("sum:1:"));
+
+        let results = results.into_inner();
+        let outcomes = |run: &Vec<CaseResult>| -> Vec<(usize, Outcome)> {
+            run.iter().map(|r| (r.line, r.outcome.clone())).collect()
+        };
+        assert_eq!(
+            outcomes(&results[0]),
+            vec![
+                (1, Outcome::Passed),
+                (5, Outcome::Passed),
+                (9, Outcome::Passed)
+            ]
+        );
+        assert_eq!(
+            outcomes(&results[1]),
+            vec![
+                (1, Outcome::Failed("2\n".into())),
+                (5, Outcome::Failed("4\n".into())),
+                (9, Outcome::Failed("8\n".into()))
+            ]
+        );
+    }
+
+    #[test]
+    fn parallel() {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("parallel");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..8 {
+            let contents = format!("echo\nfile {}\n----\nfile {}\n\necho\nfail\n----\n", i, i);
+            fs::write(dir.join(format!("f{}", i)), contents).unwrap();
+        }
+        let dir = dir.to_str().unwrap();
+
+        let parallel = walk_failure_with(Walk::new(dir), |walk| {
+            walk.run_parallel(4, || {
+                // Each file gets its own handler.
+                let mut cases = 0;
+                move |f: &mut datadriven::TestFile| {
+                    // Finish files out of order, to make sure the failures still come out in
+                    // order.
+                    let n: u64 = f.filename[f.filename.len() - 1..].parse().unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(8 - n));
+                    f.run(|s| {
+                        cases += 1;
+                        assert!(cases <= 2);
+                        format!("{}\n", s.input.trim())
+                    });
+                }
+            })
+        });
+        assert_eq!(parallel, walk_failure(Walk::new(dir)));
+        assert_eq!(parallel.matches("failure:").count(), 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn spawned() {
+        let run = |dir: &str| {
+            Walk::new(dir).run_async_spawned(4, tokio::spawn, |mut f| async move {
+                f.run_async(|s| async move {
+                    if s.directive == "panic" {
+                        panic!("{}", s.input.trim());
+                    }
+                    format!("{}\n", s.input.trim())
+                })
+                .await;
+                // A panic outside of a test case is attributed to the file.
+                assert!(!f.filename.ends_with("then_this"), "not this file");
+                f
+            })
+        };
+        let result = AssertUnwindSafe(run("tests/testdata_panics"))
+            .catch_unwind()
+            .await;
+        let msg = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.contains("tests/testdata_panics/panic:6:\nboom\n\npanicked: boom"));
+        assert!(msg.contains("tests/testdata_panics/then_this:\npanicked: not this file"));
+    }
+
+    #[test]
+    fn report() {
+        let report = try_walk("tests/testdata_filter", |f| {
+            let mut total = 0;
+            f.run(|s| match s.directive.as_str() {
+                "add" => {
+                    total += s.input.trim().parse::<i64>().unwrap();
+                    Ok(format!("{}\n", total))
+                }
+                "read" => Ok(format!("{}\n", total)),
+                _ => Err("not what was expected".to_string()),
+            })
+        });
+        assert!(!report.passed());
+        assert_eq!(report.files.len(), 1);
+        let file = &report.files[0];
+        assert_eq!(file.filename, "tests/testdata_filter/counter");
+        assert_eq!(file.failures.len(), 1);
+        assert_eq!(
+            report.failures().collect::<Vec<_>>(),
+            vec![&file.failures[0]]
+        );
+
+        let outcomes: Vec<_> = report
+            .cases()
+            .map(|(_, case)| (case.line, case.directive.as_str(), case.outcome.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, "add", Outcome::Passed),
+                (6, "add", Outcome::Passed),
+                (
+                    11,
+                    "wrong",
+                    Outcome::Errored("not what was expected".into())
+                ),
+                (15, "add", Outcome::Passed),
+                (20, "read", Outcome::Passed),
+            ]
+        );
+        assert_eq!(file.cases[1].input, "2\n");
+        assert_eq!(file.cases[1].expected, "3\n");
+        assert!(file.duration >= file.cases.iter().map(|c| c.duration).sum());
+    }
+
+    #[test]
+    fn report_unparsable() {
+        let report = try_walk("tests/testdata_unparsable", |f| f.run(|s| s.input.clone()));
+        assert!(!report.passed());
+        assert!(report.errors.is_empty());
+        let files: Vec<_> = report
+            .files
+            .iter()
+            .map(|f| (f.filename.as_str(), f.cases.len(), f.failures.len()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("tests/testdata_unparsable/broken", 0, 1),
+                ("tests/testdata_unparsable/good", 1, 0),
+            ]
+        );
+        assert!(
+            report.files[0].failures[0]
+                .starts_with("failure:\ntests/testdata_unparsable/broken:1: "),
+            "{}",
+            report.files[0].failures[0]
+        );
+    }
+
+    #[test]
+    fn junit() {
+        let path = scratch_file("junit", "").with_file_name("report.xml");
+        let report = Walk::new("tests/testdata_filter")
+            .junit(&path)
+            .try_run(|f| {
+                let mut total = 0;
+                f.run(|s| match s.directive.as_str() {
+                    "add" => {
+                        total += s.input.trim().parse::<i64>().unwrap();
+                        Ok(format!("{}\n", total))
+                    }
+                    "read" => Ok(format!("{}\n", total)),
+                    _ => Err("<not> what was expected".to_string()),
+                })
+            });
+        assert!(!report.passed());
+        Walk::new("tests/testdata_macro")
+            .junit(&path)
+            .run(|f| f.run(|s| format!("{}\n", s.input.trim())));
+
+        // The second walk added to the report rather than replacing it.
+        let xml = fs::read_to_string(&path).unwrap();
+        assert!(xml.starts_with("<?xml"));
+        assert!(
+            xml.contains("<testsuites name=\"datadriven\" tests=\"8\" failures=\"0\" errors=\"1\"")
+        );
+        assert!(xml.contains("<testsuite name=\"tests/testdata_filter/counter\" tests=\"5\""));
+        assert!(xml.contains("<testsuite name=\"tests/testdata_macro/simple\""));
+        assert!(
+            xml.contains("<testcase name=\"add@6\" classname=\"tests/testdata_filter/counter\"")
+        );
+        assert!(xml.contains("<error message=\"&lt;not&gt; what was expected\">"));
+    }
+
+    // Run by `junit_concurrent`, with `DATADRIVEN_JUNIT` set.
+    #[test]
+    #[ignore]
+    fn junit_concurrent_child() {
+        let file = std::env::var("JUNIT_CONCURRENT_FILE").unwrap();
+        Walk::new(&file).run(|f| f.run(|s| s.input.clone()));
+    }
+
+    #[test]
+    fn junit_concurrent() {
+        // Test binaries writing to the same report at once, as `cargo nextest` has them do.
+        let path = scratch_file("junit_concurrent", "").with_file_name("report.xml");
+        let children: Vec<_> = (0..8)
+            .map(|i| {
+                let file = scratch_file(&format!("junit_concurrent_{}", i), "echo\nhi\n----\nhi\n");
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["--ignored", "--exact", "tests::junit_concurrent_child"])
+                    .env("DATADRIVEN_JUNIT", &path)
+                    .env("JUNIT_CONCURRENT_FILE", file)
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+        let xml = fs::read_to_string(&path).unwrap();
+        assert_eq!(xml.matches("<testsuite ").count(), 8, "{}", xml);
+        assert!(xml.contains("<testsuites name=\"datadriven\" tests=\"8\" failures=\"0\""));
+    }
+
+    #[test]
+    fn junit_merges_other_binaries() {
+        // What another test binary left behind, including a suite for a file this run reports
+        // again.
+        let earlier = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <testsuites name=\"datadriven\" tests=\"3\" failures=\"1\" errors=\"0\" skipped=\"0\" time=\"0.000\">\n  \
+            <testsuite name=\"tests/other/file\" tests=\"2\" failures=\"1\" errors=\"0\" skipped=\"0\" time=\"0.500\">\n    \
+            <testcase name=\"echo@1\"/>\n  </testsuite>\n  \
+            <testsuite name=\"tests/testdata_macro/simple\" tests=\"1\" failures=\"0\" errors=\"0\" skipped=\"0\" time=\"0.000\">\n  \
+            </testsuite>\n</testsuites>\n";
+        let path = scratch_file("junit_merges", earlier);
+        Walk::new("tests/testdata_macro/simple")
+            .junit(&path)
+            .try_run(|f| f.run(|_| "goodbye\n".to_string()));
+
+        let xml = fs::read_to_string(&path).unwrap();
+        assert!(
+            xml.contains("<testsuites name=\"datadriven\" tests=\"3\" failures=\"2\" errors=\"0\""),
+            "{}",
+            xml
+        );
+        assert!(xml.contains("<testsuite name=\"tests/other/file\""));
+        assert_eq!(
+            xml.matches("tests/testdata_macro/simple\" tests=").count(),
+            1
+        );
+        // The diff is numbered by where the expected output is in the file.
+        assert!(xml.contains("@@ -4,1 +4,1 @@"), "{}", xml);
+    }
+
+    #[test]
+    fn events() {
+        let path = scratch_file("events", "").with_file_name("events.jsonl");
+        Walk::new("tests/testdata_filter")
+            .events(&path)
+            .try_run(|f| {
+                let mut total = 0;
+                f.run(|s| match s.directive.as_str() {
+                    "add" => {
+                        total += s.input.trim().parse::<i64>().unwrap();
+                        Ok(format!("{}\n", total))
+                    }
+                    "read" => Ok("8\n".to_string()),
+                    _ => Err("not what was expected".to_string()),
+                })
+            });
+
+        let events = fs::read_to_string(&path).unwrap();
+        let events: Vec<_> = events.lines().collect();
+        let kinds: Vec<_> = events
+            .iter()
+            .map(|e| e.split('"').nth(3).unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "file_started",
+                "case_started",
+                "case_passed",
+                "case_started",
+                "case_passed",
+                "case_started",
+                "case_errored",
+                "case_started",
+                "case_passed",
+                "case_started",
+                "case_failed",
+                "file_finished",
+                "summary",
+            ]
+        );
+        assert!(events[1].contains(r#""file":"tests/testdata_filter/counter","line":1"#));
+        assert!(events[6].contains(r#""error":"not what was expected""#));
+        assert!(events[10].contains(r#""expected":"7\n","actual":"8\n""#));
+        assert!(events[11].contains(r#""passed":false"#));
+        assert!(events[12].contains(r#""files":1,"cases":5,"cases_passed":3"#));
+    }
+
+    #[test]
+    fn events_append() {
+        // Events from earlier walks, like those in the other test binaries `cargo test` runs, stay.
+        let path = scratch_file("events_append", "").with_file_name("events.jsonl");
+        fs::write(&path, "{\"event\":\"summary\"}\n").unwrap();
+        Walk::new("tests/testdata_macro")
+            .events(&path)
+            .run(|f| f.run(|s| format!("{}\n", s.input.trim())));
+        let events = fs::read_to_string(&path).unwrap();
+        let events: Vec<_> = events.lines().collect();
+        assert_eq!(events[0], "{\"event\":\"summary\"}");
+        assert!(events[1].starts_with("{\"event\":\"file_started\""));
+        assert!(events.last().unwrap().starts_with("{\"event\":\"summary\""));
+    }
+
+    // Records everything a walk reports.
+    #[derive(Default, Clone)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl Reporter for Log {
+        fn on_file_start(&self, filename: &str) {
+            self.push(format!("start {}", filename));
+        }
+
+        fn on_case_start(&self, _filename: &str, case: &TestCase) {
+            self.push(format!("{}@{} started", case.directive, case.line()));
+        }
+
+        fn on_case_result(&self, _filename: &str, case: &CaseResult) {
+            self.push(format!(
+                "{}@{} {:?}",
+                case.directive, case.line, case.outcome
+            ));
+        }
+
+        fn on_rewrite(&self, _filename: &str, contents: &str) {
+            self.push(format!("rewrite {:?}", contents));
+        }
+
+        fn on_file_finish(&self, file: &FileReport) {
+            self.push(format!("finish {}", file.failures.len()));
+        }
+
+        fn on_finish(&self, report: &Report) {
+            self.push(format!("done {}", report.passed()));
+        }
+    }
+
+    impl Log {
+        fn push(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn reporter() {
+        let file = scratch_file("reporter", "echo\nhi\n----\nhi\n\necho\nbye\n----\nhi\n");
+        let filename = file.to_str().unwrap();
+        let log = Log::default();
+
+        // The default reporter panics once everything else has been told about the walk.
+        let msg = walk_failure(Walk::new(filename).reporter(log.clone()));
+        assert!(msg.contains("bye"));
+        assert_eq!(
+            log.take(),
+            vec![
+                format!("start {}", filename),
+                "echo@1 started".to_string(),
+                "echo@1 Passed".to_string(),
+                "echo@6 started".to_string(),
+                "echo@6 Failed(\"bye\\n\")".to_string(),
+                "finish 1".to_string(),
+                "done false".to_string(),
+            ]
+        );
+
+        Walk::new(filename)
+            .rewrite(Some(RewriteMode::All))
+            .reporter(log.clone())
+            .run(|f| f.run(|s| s.input.clone()));
+        let events = log.take();
+        assert_eq!(
+            events[5],
+            "rewrite \"echo\\nhi\\n----\\nhi\\n\\necho\\nbye\\n----\\nbye\\n\""
+        );
+        assert_eq!(events.last().unwrap(), "done true");
+    }
+
+    #[test]
+    fn empty() {
+        let msg = walk_failure(Walk::new("tests/testdata_renamed"));
+        assert!(msg.contains("no test files found in tests/testdata_renamed"));
+
+        let report = Walk::new("tests/testdata_filter").try_run(|_| ());
+        assert_eq!(
+            report.errors,
+            vec!["failure:\nno test cases ran in tests/testdata_filter\n".to_string()]
+        );
+        assert!(!report.passed());
+
+        let report = Walk::new("tests/testdata_filter")
+            .allow_empty(true)
+            .try_run(|_| ());
+        assert!(report.passed());
+        Walk::new("tests/testdata_renamed")
+            .allow_empty(true)
+            .run(|_| ());
+    }
+
+    #[test]
+    fn summary() {
+        let summary = try_walk("tests/testdata_filter", |f| {
+            f.run(|s| match s.directive.as_str() {
+                "add" => Ok(s.input.clone()),
+                _ => Err("not what was expected"),
+            })
+        })
+        .summary();
+        assert_eq!(
+            summary,
+            Summary {
+                files: 1,
+                cases: 5,
+                passed: 1,
+                failed: 3,
+                skipped: 1,
+                rewritten: 0,
+                duration: summary.duration,
+            }
+        );
+        assert!(summary.to_string().starts_with(
+            "datadriven: 1 file, 5 test cases: 1 passed, 3 failed, 1 skipped, 0 rewritten; \
+             finished in "
+        ));
+    }
+
+    #[test]
+    fn slowest() {
+        let case = |line: usize, ms: u64| CaseResult {
+            line,
+            directive: "echo".to_string(),
+            input: String::new(),
+            expected: String::new(),
+            expected_line: line + 2,
+            outcome: Outcome::Passed,
+            duration: std::time::Duration::from_millis(ms),
+        };
+        let file = |filename: &str, cases: Vec<CaseResult>| FileReport {
+            filename: filename.to_string(),
+            duration: cases.iter().map(|c| c.duration).sum(),
+            cases,
+            failures: Vec::new(),
+            rewritten: false,
+        };
+        let report = Report {
+            files: vec![
+                file("a", vec![case(1, 20), case(5, 50), case(9, 10)]),
+                file("b", vec![case(1, 40), case(5, 30)]),
+                file("c", vec![case(1, 5)]),
+            ],
+            ..Report::default()
+        };
+        let slowest: Vec<_> = report
+            .slowest_cases(3)
+            .into_iter()
+            .map(|(file, case)| format!("{}:{}", file.filename, case.line))
+            .collect();
+        assert_eq!(slowest, vec!["a:5", "b:1", "b:5"]);
+        let slowest: Vec<_> = report
+            .slowest_files(5)
+            .into_iter()
+            .map(|file| file.filename.as_str())
+            .collect();
+        assert_eq!(slowest, vec!["a", "b", "c"]);
+
+        // The reporters only print, so all there is to check is that they don't get in the way.
+        let report = Walk::new("tests/testdata_macro")
+            .warn_slower_than(std::time::Duration::from_secs(60))
+            .report_slowest(1)
+            .try_run(|f| f.run(|s| format!("{}\n", s.input.trim())));
+        assert!(report.passed());
+    }
+
+    #[test]
+    fn file_order() {
+        let order = |walk: Walk| {
+            let mut files = Vec::new();
+            walk.allow_empty(true)
+                .run(|f| files.push(f.filename.clone()));
+            files
+        };
+        let sorted = order(Walk::new("tests/testdata"));
+        assert_eq!(
+            sorted,
+            vec![
+                "tests/testdata/args",
+                "tests/testdata/excluded",
+                "tests/testdata/multiline",
+                "tests/testdata/nested/nested_file",
+                "tests/testdata/nonewline",
+                "tests/testdata/unicode",
+            ]
+        );
+
+        // A seed always gives the same order.
+        let shuffled = order(Walk::new("tests/testdata").shuffle(42));
+        assert_eq!(shuffled, order(Walk::new("tests/testdata").shuffle(42)));
+        assert_ne!(shuffled, sorted);
+        let mut unshuffled = shuffled.clone();
+        unshuffled.sort();
+        assert_eq!(unshuffled, sorted);
+
+        // The seed is in the report, and in the failure, so that the order can be reproduced.
+        let walk = || Walk::new("tests/testdata_keep_going").shuffle(42);
+        assert_eq!(
+            walk().try_run(|f| f.run(|_| String::new())).shuffle,
+            Some(42)
+        );
+        let msg = walk_failure(walk());
+        assert!(msg.contains("DATADRIVEN_SHUFFLE=42"), "{}", msg);
+    }
+
+    // Run by `random_shuffle` with `DATADRIVEN_SHUFFLE=random`.
+    #[test]
+    #[ignore]
+    fn random_shuffle_child() {
+        for _ in 0..2 {
+            let report = Walk::new("tests/testdata").try_run(|f| f.run(|s| s.input.clone()));
+            println!("seed {}", report.shuffle.unwrap());
+        }
+    }
+
+    #[test]
+    fn random_shuffle() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--ignored", "--exact", "--nocapture"])
+            .arg("tests::random_shuffle_child")
+            .env("DATADRIVEN_SHUFFLE", "random")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let seeds: Vec<&str> = stdout
+            .lines()
+            .filter_map(|l| l.split("seed ").nth(1))
+            .collect();
+        // Every walk in the process uses the same seed, which is printed once.
+        assert_eq!(seeds.len(), 2, "{}", stdout);
+        assert_eq!(seeds[0], seeds[1]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let printed = format!("shuffling test files with DATADRIVEN_SHUFFLE={}", seeds[0]);
+        assert_eq!(stderr.matches(&printed).count(), 1, "{}", stderr);
+    }
+
+    #[datadriven::test("tests/testdata_macro")]
+    fn generated(f: &mut datadriven::TestFile) {
+        f.run(|s| format!("{}\n", s.input.trim()));
+    }
+
+    #[test]
+    fn generated_names() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--list", "generated::"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut tests: Vec<_> = stdout
+            .lines()
+            .filter_map(|l| l.strip_suffix(": test"))
+            .filter(|t| *t != "tests::generated_names")
+            .collect();
+        tests.sort();
+        assert_eq!(
+            tests,
+            vec![
+                "tests::generated::nested::more_cases",
+                "tests::generated::simple"
+            ]
+        );
+    }
 }